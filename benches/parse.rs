@@ -0,0 +1,92 @@
+//! Compare parsing into [`ConnectionString`] and [`ConnectionStringRef`].
+//!
+//! Besides the timings, this prints how many allocations each takes per
+//! connection string, counted with a wrapper around the system allocator.
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use postgres_conn_str::{ConnectionString, ConnectionStringRef};
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+struct CountingAlloc;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+#[allow(unsafe_code)]
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout);
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+/// Tenant DSNs of the sort a connection pool reads at startup.
+fn dsns() -> Vec<String> {
+    (0..1000)
+        .map(|n| match n % 4 {
+            0 => format!("postgresql://tenant_{n}:s3cret{n}@db{}.internal:5432/tenant_{n}?sslmode=require&application_name=pool", n % 8),
+            1 => format!("postgres://tenant_{n}@primary,replica:5433/tenant_{n}?target_session_attrs=read-write&connect_timeout=5"),
+            2 => format!("host=/var/run/postgresql,db{} port=,5433 dbname=tenant_{n} user=tenant_{n} options='-c search_path=tenant_{n}'", n % 8),
+            _ => format!("postgresql://tenant_{n}:p%40ss@%2Fvar%2Frun%2Fpostgresql/tenant_{n}"),
+        })
+        .collect()
+}
+
+/// Count the allocations `f` makes per DSN.
+fn allocations_per_dsn(dsns: &[String], f: impl Fn(&str)) -> f64 {
+    let before = ALLOCATIONS.load(Ordering::Relaxed);
+    for dsn in dsns {
+        f(dsn);
+    }
+    let count = ALLOCATIONS.load(Ordering::Relaxed) - before;
+
+    #[allow(clippy::cast_precision_loss)]
+    let per_dsn = count as f64 / dsns.len() as f64;
+    per_dsn
+}
+
+fn parse(c: &mut Criterion) {
+    let dsns = dsns();
+
+    let owned = allocations_per_dsn(&dsns, |dsn| {
+        black_box(dsn.parse::<ConnectionString>().unwrap());
+    });
+    let borrowed = allocations_per_dsn(&dsns, |dsn| {
+        black_box(ConnectionStringRef::parse(dsn).unwrap());
+    });
+    println!("allocations per DSN: ConnectionString {owned:.1}, ConnectionStringRef {borrowed:.1}");
+
+    let mut group = c.benchmark_group("parse");
+    group.bench_function("ConnectionString", |b| {
+        b.iter(|| {
+            for dsn in &dsns {
+                black_box(dsn.parse::<ConnectionString>().unwrap());
+            }
+        });
+    });
+    group.bench_function("ConnectionStringRef", |b| {
+        b.iter(|| {
+            for dsn in &dsns {
+                black_box(ConnectionStringRef::parse(dsn).unwrap());
+            }
+        });
+    });
+    group.finish();
+}
+
+criterion_group!(benches, parse);
+criterion_main!(benches);
@@ -1,50 +1,85 @@
-use anyhow::Result;
-use clap::Parser;
+use anyhow::{anyhow, Result};
+use clap::{ArgAction, Parser, Subcommand};
+use postgres_conn_str::{ConnectionString, PgPass, StdEnv};
 use rand::seq::SliceRandom;
-use tracing::{debug, instrument};
+use std::path::PathBuf;
+use tracing::debug;
 
 /// Parse one or more connection strings from an input string.
 ///
 /// env!("CARGO_BIN_NAME") accepts either a single connection string, or a character-separated list
-/// of strings (defaults to comma-separated).
-#[derive(Debug, Default, Parser)]
+/// of URIs (defaults to comma-separated). A keyword/value string is never split.
+#[derive(Debug, Parser)]
+#[command(subcommand_negates_reqs = true)]
 struct Config {
     /// Increase log verbosity. Defaults to warn.
     ///
     /// To decrease logging verbosity, set the RUST_LOG=error.
-    #[clap(short, long, parse(from_occurrences))]
-    verbose: usize,
+    #[arg(short, long, action = ArgAction::Count, global = true)]
+    verbose: u8,
 
     /// Character used to separate connection strings.
-    #[clap(short, long, default_value = ",")]
+    #[arg(short, long, default_value = ",")]
     separator: String,
 
     /// Limit the number of returned connection strings.
     ///
     /// Combine with --randomize to select a random connection string.
-    #[clap(short, long)]
+    #[arg(short, long)]
     limit: Option<usize>,
 
     /// Return connection string(s) in random order.
     ///
     /// Combine with --limit to select a single random connection string.
-    #[clap(short, long)]
+    #[arg(short, long)]
     randomize: bool,
 
-    connection_string: String,
+    #[arg(required = true)]
+    connection_string: Option<String>,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Manage libpq password files.
+    #[command(subcommand)]
+    Pgpass(PgpassCommand),
+}
+
+#[derive(Debug, Subcommand)]
+enum PgpassCommand {
+    /// Add or update the password file entries for a connection string's
+    /// password, then print the connection string without it.
+    Add {
+        /// The password file to update. Defaults to the one libpq would read.
+        #[arg(short, long)]
+        file: Option<PathBuf>,
+
+        connection_string: String,
+    },
 }
 
-#[instrument]
 fn main() -> Result<()> {
     // Parse the config from input args/environment.
     let config = Config::parse();
 
     // Set up logging. Defaults to WARN, increasing from there.
-    jacklog::from_level(2 + config.verbose, None)?;
+    jacklog::from_level(2 + config.verbose, None::<&str>)?;
+
+    match config.command {
+        Some(Command::Pgpass(PgpassCommand::Add {
+            file,
+            connection_string,
+        })) => pgpass_add(file, &connection_string),
+        None => parse(&config),
+    }
+}
 
-    debug!(connection_string = ?config.connection_string);
-    let mut parsed =
-        pg_connection_string::from_multi_str(&config.connection_string, &config.separator)?;
+fn parse(config: &Config) -> Result<()> {
+    let connection_string = config.connection_string.as_deref().unwrap_or_default();
+    let mut parsed = postgres_conn_str::from_multi_str(connection_string, &config.separator)?;
     debug!(?parsed);
 
     if config.randomize {
@@ -68,3 +103,22 @@ fn main() -> Result<()> {
 
     Ok(())
 }
+
+fn pgpass_add(file: Option<PathBuf>, connection_string: &str) -> Result<()> {
+    let mut conn: ConnectionString = connection_string.parse()?;
+
+    if conn.pgpass_entries().is_empty() {
+        return Err(anyhow!("the connection string has no password"));
+    }
+
+    let file = file
+        .or_else(|| PgPass::default_path(&conn, &StdEnv))
+        .ok_or_else(|| anyhow!("no password file given, and PGPASSFILE and HOME are unset"))?;
+
+    conn.move_password_to_pgpass(&file)?;
+    debug!(file = %file.display(), "added password file entries");
+
+    println!("{conn}");
+
+    Ok(())
+}
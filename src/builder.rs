@@ -0,0 +1,416 @@
+/*!
+Build connection strings piece by piece.
+*/
+
+use crate::{
+    ChannelBinding, ConnectionOptions, ConnectionString, GssEncMode, Host, HostSpec, InvalidOption,
+    LoadBalanceHosts, Parameter, Replication, Secret, SslCertMode, SslMode, SslNegotiation,
+    SslProtocolVersion, TargetSessionAttrs, LIBPQ_KEYWORDS,
+};
+use std::{fmt::Display, path::PathBuf, time::Duration};
+
+/// A builder for [`ConnectionString`], created by
+/// [`ConnectionString::builder`].
+///
+/// Nothing is checked until [`ConnectionStringBuilder::build`], which reports
+/// the first problem found.
+///
+/// # Example
+///
+/// ```
+/// use postgres_conn_str::{ConnectionString, SslMode};
+///
+/// let conn = ConnectionString::builder()
+///     .host_port("db1", 5433)
+///     .socket_dir("/var/run/postgresql")
+///     .user("jack")
+///     .dbname("mydb")
+///     .sslmode(SslMode::Require)
+///     .param("application_name", "reports")
+///     .build()
+///     .unwrap();
+///
+/// assert_eq!(
+///     conn.to_string(),
+///     "postgresql://jack@db1:5433,%2Fvar%2Frun%2Fpostgresql/mydb?sslmode=require&application_name=reports",
+/// );
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct ConnectionStringBuilder {
+    conn: ConnectionString,
+    /// Every keyword that's been set, to catch duplicates.
+    keywords: Vec<String>,
+    error: Option<BuildError>,
+}
+
+impl ConnectionStringBuilder {
+    /// Add a host, which may be a host name, an IP address or, if it starts
+    /// with `/`, a Unix-domain socket directory. Give it without a port or
+    /// brackets, and add several hosts one at a time.
+    #[must_use]
+    pub fn host(self, host: impl AsRef<str>) -> Self {
+        self.push_host(host.as_ref(), None)
+    }
+
+    /// Add a host with a port.
+    #[must_use]
+    pub fn host_port(self, host: impl AsRef<str>, port: u16) -> Self {
+        self.push_host(host.as_ref(), Some(port))
+    }
+
+    /// Add a Unix-domain socket directory as a host.
+    #[must_use]
+    pub fn socket_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.conn.hostspecs.push(HostSpec {
+            host: Host::Path(dir.into()),
+            port: None,
+        });
+        self
+    }
+
+    /// Set the user name.
+    #[must_use]
+    pub fn user(mut self, user: impl Into<String>) -> Self {
+        self.set("user");
+        self.conn.user = Some(user.into());
+        self
+    }
+
+    /// Set the password.
+    #[must_use]
+    pub fn password(mut self, password: impl Into<Secret>) -> Self {
+        self.set("password");
+        self.conn.password = Some(password.into());
+        self
+    }
+
+    /// Set the database name.
+    #[must_use]
+    pub fn dbname(mut self, dbname: impl Into<String>) -> Self {
+        self.set("dbname");
+        self.conn.database = Some(dbname.into());
+        self
+    }
+
+    /// Set a connection parameter. `dbname`, `user` and `password` are the
+    /// same as calling their setters; hosts and ports have to be added with
+    /// [`ConnectionStringBuilder::host`] and friends.
+    #[must_use]
+    pub fn param(self, keyword: impl Into<String>, value: impl Into<String>) -> Self {
+        let keyword = keyword.into();
+        let value = value.into();
+
+        match keyword.as_str() {
+            "dbname" => self.dbname(value),
+            "user" => self.user(value),
+            "password" => self.password(value),
+            "host" | "port" => self.fail(BuildError::HostKeyword(keyword)),
+            _ => {
+                let mut out = self;
+                out.set(&keyword);
+                out.conn.parameters.push(Parameter { keyword, value });
+                out
+            }
+        }
+    }
+
+    /// Set `sslmode`.
+    #[must_use]
+    pub fn sslmode(self, mode: SslMode) -> Self {
+        self.param("sslmode", mode.as_str())
+    }
+
+    /// Set `sslnegotiation`.
+    #[must_use]
+    pub fn sslnegotiation(self, negotiation: SslNegotiation) -> Self {
+        self.param("sslnegotiation", negotiation.as_str())
+    }
+
+    /// Set `sslcertmode`.
+    #[must_use]
+    pub fn sslcertmode(self, mode: SslCertMode) -> Self {
+        self.param("sslcertmode", mode.as_str())
+    }
+
+    /// Set `ssl_min_protocol_version`.
+    #[must_use]
+    pub fn ssl_min_protocol_version(self, version: SslProtocolVersion) -> Self {
+        self.param("ssl_min_protocol_version", version.as_str())
+    }
+
+    /// Set `ssl_max_protocol_version`.
+    #[must_use]
+    pub fn ssl_max_protocol_version(self, version: SslProtocolVersion) -> Self {
+        self.param("ssl_max_protocol_version", version.as_str())
+    }
+
+    /// Set `gssencmode`.
+    #[must_use]
+    pub fn gssencmode(self, mode: GssEncMode) -> Self {
+        self.param("gssencmode", mode.as_str())
+    }
+
+    /// Set `channel_binding`.
+    #[must_use]
+    pub fn channel_binding(self, binding: ChannelBinding) -> Self {
+        self.param("channel_binding", binding.as_str())
+    }
+
+    /// Set `target_session_attrs`.
+    #[must_use]
+    pub fn target_session_attrs(self, attrs: TargetSessionAttrs) -> Self {
+        self.param("target_session_attrs", attrs.as_str())
+    }
+
+    /// Set `load_balance_hosts`.
+    #[must_use]
+    pub fn load_balance_hosts(self, order: LoadBalanceHosts) -> Self {
+        self.param("load_balance_hosts", order.as_str())
+    }
+
+    /// Set `replication`.
+    #[must_use]
+    pub fn replication(self, replication: Replication) -> Self {
+        self.param("replication", replication.as_str())
+    }
+
+    /// Set `connect_timeout`. libpq only takes whole seconds, so this is
+    /// rounded up.
+    #[must_use]
+    pub fn connect_timeout(self, timeout: Duration) -> Self {
+        let secs = timeout.as_secs() + u64::from(timeout.subsec_nanos() > 0);
+        self.param("connect_timeout", secs.to_string())
+    }
+
+    /// Set `application_name`.
+    #[must_use]
+    pub fn application_name(self, name: impl Into<String>) -> Self {
+        self.param("application_name", name)
+    }
+
+    /// Check the connection string and build it.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a host is empty or invalid, a keyword is unknown or
+    /// set more than once, or a parameter has a value libpq wouldn't accept.
+    pub fn build(self) -> Result<ConnectionString, BuildError> {
+        if let Some(e) = self.error {
+            return Err(e);
+        }
+
+        for (n, keyword) in self.keywords.iter().enumerate() {
+            if !LIBPQ_KEYWORDS.contains(&keyword.as_str()) {
+                return Err(BuildError::UnknownKeyword(keyword.clone()));
+            }
+            if self.keywords[..n].contains(keyword) {
+                return Err(BuildError::Duplicate(keyword.clone()));
+            }
+        }
+
+        ConnectionOptions::from_parameters(&self.conn.parameters)?;
+
+        Ok(self.conn)
+    }
+
+    fn push_host(mut self, host: &str, port: Option<u16>) -> Self {
+        match Host::from_value(host) {
+            // A list can't be told apart from several hosts, and anything
+            // else with a `:` would be read back as a host and port.
+            Some(Host::Name(name)) if name.contains([',', ':']) || name.starts_with('[') => {
+                self.fail(BuildError::InvalidHost(name))
+            }
+            Some(host) if host.to_value().contains(',') => {
+                self.fail(BuildError::InvalidHost(host.to_value()))
+            }
+            Some(host) => {
+                self.conn.hostspecs.push(HostSpec { host, port });
+                self
+            }
+            None => self.fail(BuildError::EmptyHost),
+        }
+    }
+
+    /// Record that a keyword has been set.
+    fn set(&mut self, keyword: &str) {
+        self.keywords.push(keyword.to_string());
+    }
+
+    /// Record an error, keeping the first one.
+    fn fail(mut self, error: BuildError) -> Self {
+        self.error.get_or_insert(error);
+        self
+    }
+}
+
+/// An error building a connection string.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum BuildError {
+    /// A host name is empty.
+    EmptyHost,
+    /// A host isn't a single host name, IP address or socket directory, e.g.
+    /// it's a comma-separated list, has a port, or is an IPv6 address in
+    /// brackets.
+    InvalidHost(String),
+    /// `host` or `port` was passed to [`ConnectionStringBuilder::param`].
+    HostKeyword(String),
+    /// A keyword libpq doesn't know.
+    UnknownKeyword(String),
+    /// A keyword was set more than once.
+    Duplicate(String),
+    /// A parameter has a value libpq wouldn't accept.
+    InvalidValue(InvalidOption),
+}
+
+impl From<InvalidOption> for BuildError {
+    fn from(e: InvalidOption) -> Self {
+        BuildError::InvalidValue(e)
+    }
+}
+
+impl Display for BuildError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BuildError::EmptyHost => write!(f, "empty host name"),
+            BuildError::InvalidHost(host) => {
+                write!(
+                    f,
+                    "invalid host {host:?}; add each host and its port separately"
+                )
+            }
+            BuildError::HostKeyword(keyword) => {
+                write!(
+                    f,
+                    "{keyword:?} can't be set as a parameter; add hosts instead"
+                )
+            }
+            BuildError::UnknownKeyword(keyword) => {
+                write!(f, "unknown connection parameter {keyword:?}")
+            }
+            BuildError::Duplicate(keyword) => write!(f, "{keyword:?} is set more than once"),
+            BuildError::InvalidValue(e) => e.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for BuildError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            BuildError::InvalidValue(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    #[test]
+    fn test_build() {
+        let conn = ConnectionString::builder()
+            .host("db1")
+            .host_port("::1", 5433)
+            .socket_dir("/tmp")
+            .user("jack")
+            .password("p@ss word")
+            .dbname("mydb")
+            .sslmode(SslMode::VerifyFull)
+            .target_session_attrs(TargetSessionAttrs::ReadWrite)
+            .connect_timeout(Duration::from_millis(2500))
+            .application_name("reports")
+            .param("options", "-c geqo=off")
+            .build()
+            .unwrap();
+
+        assert_eq!(
+            conn,
+            ConnectionString::from_str(
+                "host=db1,::1,/tmp port=,5433, user=jack password='p@ss word' dbname=mydb \
+                 sslmode=verify-full target_session_attrs=read-write connect_timeout=3 \
+                 application_name=reports options='-c geqo=off'"
+            )
+            .unwrap()
+        );
+
+        // The components can be set as parameters too.
+        assert_eq!(
+            ConnectionString::builder()
+                .param("dbname", "mydb")
+                .param("user", "jack")
+                .build()
+                .unwrap(),
+            ConnectionString::from_str("dbname=mydb user=jack").unwrap()
+        );
+        assert_eq!(
+            ConnectionString::builder().build().unwrap(),
+            ConnectionString::default()
+        );
+
+        // IPv6 addresses, with or without a zone, are fine.
+        let conn = ConnectionString::builder()
+            .host("fe80::1%eth0")
+            .host_port("::1", 5433)
+            .build()
+            .unwrap();
+        assert_eq!(
+            ConnectionString::from_str(&conn.keyword_value().to_string()).unwrap(),
+            conn
+        );
+    }
+
+    #[test]
+    fn test_build_errors() {
+        for (builder, expect) in [
+            (ConnectionString::builder().host(""), BuildError::EmptyHost),
+            (
+                ConnectionString::builder().host("db1,db2"),
+                BuildError::InvalidHost("db1,db2".into()),
+            ),
+            (
+                ConnectionString::builder().host("db1:5432"),
+                BuildError::InvalidHost("db1:5432".into()),
+            ),
+            (
+                ConnectionString::builder().host_port("[::1]", 5432),
+                BuildError::InvalidHost("[::1]".into()),
+            ),
+            (
+                ConnectionString::builder().host("/tmp,/run"),
+                BuildError::InvalidHost("/tmp,/run".into()),
+            ),
+            (
+                ConnectionString::builder().param("port", "5432"),
+                BuildError::HostKeyword("port".into()),
+            ),
+            (
+                ConnectionString::builder().param("sslmdoe", "require"),
+                BuildError::UnknownKeyword("sslmdoe".into()),
+            ),
+            (
+                ConnectionString::builder()
+                    .sslmode(SslMode::Require)
+                    .param("sslmode", "disable"),
+                BuildError::Duplicate("sslmode".into()),
+            ),
+            (
+                ConnectionString::builder().dbname("a").param("dbname", "b"),
+                BuildError::Duplicate("dbname".into()),
+            ),
+            (
+                ConnectionString::builder().param("sslmode", "required"),
+                BuildError::InvalidValue(
+                    ConnectionOptions::from_parameters(&[Parameter {
+                        keyword: "sslmode".into(),
+                        value: "required".into(),
+                    }])
+                    .unwrap_err(),
+                ),
+            ),
+        ] {
+            assert_eq!(builder.build(), Err(expect));
+        }
+    }
+}
@@ -0,0 +1,95 @@
+/*!
+Conversions to and from other crates' connection configurations, each behind a
+feature of the same name.
+*/
+
+use crate::InvalidOption;
+use std::fmt::Display;
+
+#[cfg(feature = "deadpool-postgres")]
+mod deadpool_postgres;
+#[cfg(feature = "sqlx")]
+mod sqlx;
+#[cfg(feature = "tokio-postgres")]
+mod tokio_postgres;
+#[cfg(feature = "url")]
+mod url;
+
+#[cfg(feature = "deadpool-postgres")]
+pub use deadpool_postgres::POOL_KEYWORDS;
+
+/// An error converting a connection string to or from another crate's
+/// configuration.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ConversionError {
+    /// Parameters the other side has no way to represent, by keyword, in the
+    /// order they were found.
+    Unsupported(Vec<String>),
+    /// A parameter has a value libpq wouldn't accept.
+    InvalidValue(InvalidOption),
+}
+
+impl From<InvalidOption> for ConversionError {
+    fn from(e: InvalidOption) -> Self {
+        ConversionError::InvalidValue(e)
+    }
+}
+
+impl Display for ConversionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConversionError::Unsupported(keywords) => {
+                write!(f, "unsupported connection parameters: ")?;
+                for (n, keyword) in keywords.iter().enumerate() {
+                    if n > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{keyword}")?;
+                }
+                Ok(())
+            }
+            ConversionError::InvalidValue(e) => e.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for ConversionError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ConversionError::InvalidValue(e) => Some(e),
+            ConversionError::Unsupported(_) => None,
+        }
+    }
+}
+
+/// Collects the keywords that can't be converted, without repeats.
+#[cfg(any(
+    feature = "deadpool-postgres",
+    feature = "sqlx",
+    feature = "tokio-postgres"
+))]
+#[derive(Debug, Default)]
+pub(crate) struct Unsupported(Vec<String>);
+
+#[cfg(any(
+    feature = "deadpool-postgres",
+    feature = "sqlx",
+    feature = "tokio-postgres"
+))]
+impl Unsupported {
+    pub(crate) fn push(&mut self, keyword: &str) {
+        if !self.0.iter().any(|k| k == keyword) {
+            self.0.push(keyword.to_string());
+        }
+    }
+
+    /// Fail if anything couldn't be converted.
+    pub(crate) fn check(self) -> Result<(), ConversionError> {
+        if self.0.is_empty() {
+            Ok(())
+        } else {
+            Err(ConversionError::Unsupported(self.0))
+        }
+    }
+}
@@ -76,7 +76,7 @@ mod tests {
                     user: None,
                     password: None,
                     hostspecs: vec![HostSpec {
-                        host: "localhost".to_string(),
+                        host: "localhost".parse().unwrap(),
                         port: None,
                     }],
                     database: Some("mydb".to_string()),
@@ -0,0 +1,106 @@
+/*!
+Fill in parameters missing from a connection string, the way libpq does with
+service files and environment variables.
+*/
+
+use crate::{apply_ports, hostspecs_from_lists, ConnectionString, Parameter, ParseError};
+
+/// Fill in anything missing from `conn` from `defaults`, returning the
+/// keywords that were used. A value from the connection string always wins,
+/// and a default only applies to a keyword that wasn't given at all.
+///
+/// `host` and `port` defaults may be comma-separated lists. The port list is
+/// only used if the connection string didn't give a port for any host, and,
+/// as in libpq, an empty list is the same as none at all.
+pub(crate) fn fill_missing(
+    conn: &mut ConnectionString,
+    defaults: &[Parameter],
+) -> Result<Vec<String>, ParseError> {
+    let mut used = vec![];
+    let mut hosts = None;
+    let mut ports = None;
+
+    for Parameter { keyword, value } in defaults {
+        match keyword.as_str() {
+            // The host and port lists are handled together below.
+            "host" if !value.is_empty() => {
+                hosts = Some(value);
+                continue;
+            }
+            "port" if !value.is_empty() => {
+                ports = Some(value);
+                continue;
+            }
+            "host" | "port" => continue,
+            "dbname" if conn.database.is_none() => conn.database = Some(value.clone()),
+            "user" if conn.user.is_none() => conn.user = Some(value.clone()),
+            "password" if conn.password.is_none() => conn.password = Some(value.as_str().into()),
+            "dbname" | "user" | "password" => continue,
+            kw if conn.parameters.iter().any(|p| p.keyword == kw) => continue,
+            _ => conn.parameters.push(Parameter {
+                keyword: keyword.clone(),
+                value: value.clone(),
+            }),
+        }
+        used.push(keyword.clone());
+    }
+
+    if let Some(hosts) = hosts.filter(|_| conn.hostspecs.is_empty()) {
+        check_list(hosts, "host")?;
+        conn.hostspecs = hostspecs_from_lists(hosts, None)?;
+        used.push("host".into());
+    }
+
+    let port_param = conn.parameters.iter().position(|p| p.keyword == "port");
+
+    // There's no host to attach a port to, so it stays a parameter.
+    if conn.hostspecs.is_empty() {
+        if let Some(value) = ports.filter(|_| port_param.is_none()) {
+            check_list(value, "port")?;
+            conn.parameters.push(Parameter {
+                keyword: "port".into(),
+                value: value.clone(),
+            });
+            used.push("port".into());
+        }
+
+        return Ok(used);
+    }
+
+    let ports = match port_param {
+        // A port given without a host now has hosts to pair up with.
+        Some(n) => Some(conn.parameters.remove(n).value),
+        None if conn.hostspecs.iter().all(|spec| spec.port.is_none()) => {
+            ports.inspect(|_| used.push("port".into())).cloned()
+        }
+        None => None,
+    };
+
+    if let Some(ports) = ports {
+        check_list(&ports, "port")?;
+        apply_ports(conn.hostspecs.iter_mut().map(|spec| &mut spec.port), &ports)?;
+    }
+
+    Ok(used)
+}
+
+/// Check a `host` or `port` list that hasn't been through the parser.
+pub(crate) fn check_list(value: &str, component: &str) -> Result<(), ParseError> {
+    let mut offset = 0;
+
+    for entry in value.split(',') {
+        let valid = match component {
+            "host" => !entry.is_empty(),
+            _ => entry.is_empty() || entry.parse::<u16>().is_ok(),
+        };
+
+        if !valid {
+            return Err(ParseError::from_context(component, value, offset)
+                .expect("component names a parser context"));
+        }
+
+        offset += entry.len() + 1;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,647 @@
+/*!
+Typed access to libpq connection parameters.
+
+See [https://www.postgresql.org/docs/current/libpq-connect.html#LIBPQ-PARAMKEYWORDS](https://www.postgresql.org/docs/current/libpq-connect.html#LIBPQ-PARAMKEYWORDS).
+*/
+
+use crate::{Parameter, Secret};
+use std::{fmt::Display, net::IpAddr, path::PathBuf, str::FromStr, time::Duration};
+
+/// Every connection parameter keyword libpq understands.
+pub const LIBPQ_KEYWORDS: &[&str] = &[
+    "host",
+    "hostaddr",
+    "port",
+    "dbname",
+    "user",
+    "password",
+    "passfile",
+    "require_auth",
+    "channel_binding",
+    "connect_timeout",
+    "client_encoding",
+    "options",
+    "application_name",
+    "fallback_application_name",
+    "keepalives",
+    "keepalives_idle",
+    "keepalives_interval",
+    "keepalives_count",
+    "tcp_user_timeout",
+    "replication",
+    "gssencmode",
+    "sslmode",
+    "sslnegotiation",
+    "sslcompression",
+    "sslcert",
+    "sslkey",
+    "sslkeylogfile",
+    "sslpassword",
+    "sslcertmode",
+    "sslrootcert",
+    "sslcrl",
+    "sslcrldir",
+    "sslsni",
+    "requirepeer",
+    "ssl_min_protocol_version",
+    "ssl_max_protocol_version",
+    "min_protocol_version",
+    "max_protocol_version",
+    "krbsrvname",
+    "gsslib",
+    "gssdelegation",
+    "service",
+    "target_session_attrs",
+    "load_balance_hosts",
+    "oauth_issuer",
+    "oauth_client_id",
+    "oauth_client_secret",
+    "oauth_scope",
+];
+
+/// A connection parameter whose value libpq wouldn't accept.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InvalidOption {
+    /// The parameter's keyword.
+    pub keyword: String,
+
+    /// The rejected value.
+    pub value: String,
+
+    /// A description of the values that would have been accepted.
+    pub expected: String,
+}
+
+impl InvalidOption {
+    pub(crate) fn new(keyword: &str, value: &str, expected: impl Into<String>) -> Self {
+        InvalidOption {
+            keyword: keyword.to_string(),
+            value: value.to_string(),
+            expected: expected.into(),
+        }
+    }
+}
+
+impl Display for InvalidOption {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "invalid {} value {:?}: expected {}",
+            self.keyword, self.value, self.expected
+        )
+    }
+}
+
+impl std::error::Error for InvalidOption {}
+
+/// Define an enum for a parameter that takes one of a fixed set of values.
+macro_rules! keyword_enum {
+    (
+        $(#[$meta:meta])*
+        $name:ident($keyword:literal) {
+            $($(#[$vmeta:meta])* $variant:ident => $value:literal,)+
+        }
+    ) => {
+        $(#[$meta])*
+        #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+        pub enum $name {
+            $($(#[$vmeta])* $variant,)+
+        }
+
+        impl $name {
+            /// The value as libpq spells it.
+            #[must_use]
+            pub fn as_str(self) -> &'static str {
+                match self {
+                    $(Self::$variant => $value,)+
+                }
+            }
+        }
+
+        impl Display for $name {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                f.write_str(self.as_str())
+            }
+        }
+
+        impl FromStr for $name {
+            type Err = InvalidOption;
+
+            fn from_str(s: &str) -> Result<Self, Self::Err> {
+                match s {
+                    $($value => Ok(Self::$variant),)+
+                    _ => Err(InvalidOption::new(
+                        $keyword,
+                        s,
+                        format!("one of {}", [$($value),+].join(", ")),
+                    )),
+                }
+            }
+        }
+    };
+}
+
+keyword_enum! {
+    /// Whether and how to negotiate SSL with the server (`sslmode`).
+    SslMode("sslmode") {
+        /// Only try a non-SSL connection.
+        Disable => "disable",
+        /// Try a non-SSL connection first, then SSL.
+        Allow => "allow",
+        /// Try an SSL connection first, then non-SSL. This is libpq's default.
+        Prefer => "prefer",
+        /// Only try an SSL connection, without verifying the server.
+        Require => "require",
+        /// Only try an SSL connection, verifying the server certificate's CA.
+        VerifyCa => "verify-ca",
+        /// Only try an SSL connection, verifying the CA and the host name.
+        VerifyFull => "verify-full",
+    }
+}
+
+keyword_enum! {
+    /// How SSL is negotiated when it's used (`sslnegotiation`).
+    SslNegotiation("sslnegotiation") {
+        /// Ask the server with a PostgreSQL protocol request first.
+        Postgres => "postgres",
+        /// Start the SSL handshake straight away.
+        Direct => "direct",
+    }
+}
+
+keyword_enum! {
+    /// Whether to send a client certificate (`sslcertmode`).
+    SslCertMode("sslcertmode") {
+        /// Never send a client certificate.
+        Disable => "disable",
+        /// Send a certificate if the server asks and one is available.
+        Allow => "allow",
+        /// Fail unless a certificate is sent.
+        Require => "require",
+    }
+}
+
+keyword_enum! {
+    /// A TLS protocol version (`ssl_min_protocol_version` and
+    /// `ssl_max_protocol_version`).
+    SslProtocolVersion("ssl_min_protocol_version") {
+        Tlsv1 => "TLSv1",
+        Tlsv1_1 => "TLSv1.1",
+        Tlsv1_2 => "TLSv1.2",
+        Tlsv1_3 => "TLSv1.3",
+    }
+}
+
+keyword_enum! {
+    /// Whether to negotiate GSSAPI encryption (`gssencmode`).
+    GssEncMode("gssencmode") {
+        /// Only try a non-GSSAPI-encrypted connection.
+        Disable => "disable",
+        /// Try a GSSAPI-encrypted connection first. This is libpq's default.
+        Prefer => "prefer",
+        /// Only try a GSSAPI-encrypted connection.
+        Require => "require",
+    }
+}
+
+keyword_enum! {
+    /// Whether to use SCRAM channel binding (`channel_binding`).
+    ChannelBinding("channel_binding") {
+        /// Never use channel binding.
+        Disable => "disable",
+        /// Use channel binding if available. This is libpq's default.
+        Prefer => "prefer",
+        /// Fail unless channel binding is used.
+        Require => "require",
+    }
+}
+
+keyword_enum! {
+    /// Which kind of server a connection is acceptable to
+    /// (`target_session_attrs`).
+    TargetSessionAttrs("target_session_attrs") {
+        /// Any server. This is libpq's default.
+        Any => "any",
+        /// A server that accepts read-write transactions by default.
+        ReadWrite => "read-write",
+        /// A server that doesn't accept read-write transactions by default.
+        ReadOnly => "read-only",
+        /// A server that isn't in hot standby mode.
+        Primary => "primary",
+        /// A server in hot standby mode.
+        Standby => "standby",
+        /// A server in hot standby mode if there is one, otherwise any server.
+        PreferStandby => "prefer-standby",
+    }
+}
+
+keyword_enum! {
+    /// The order in which hosts are tried (`load_balance_hosts`).
+    LoadBalanceHosts("load_balance_hosts") {
+        /// In the order they're given. This is libpq's default.
+        Disable => "disable",
+        /// In random order.
+        Random => "random",
+    }
+}
+
+/// Which replication protocol to use (`replication`).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Replication {
+    /// A regular connection.
+    Off,
+    /// A physical replication connection.
+    Physical,
+    /// A logical replication connection to the database in `dbname`.
+    Database,
+}
+
+impl Replication {
+    /// The value as libpq spells it.
+    #[must_use]
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Replication::Off => "false",
+            Replication::Physical => "true",
+            Replication::Database => "database",
+        }
+    }
+}
+
+impl Display for Replication {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Replication {
+    type Err = InvalidOption;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "database" => Ok(Replication::Database),
+            _ => parse_bool("replication", s).map(|on| {
+                if on {
+                    Replication::Physical
+                } else {
+                    Replication::Off
+                }
+            }),
+        }
+    }
+}
+
+/// Typed view of the libpq connection parameters in a connection string.
+///
+/// The host, port, database, user and password live on
+/// [`ConnectionString`](crate::ConnectionString) itself; everything else is
+/// here. Parameters that libpq doesn't know about are ignored, and when a
+/// parameter is given more than once the last value wins, as in libpq.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[allow(clippy::struct_excessive_bools)]
+pub struct ConnectionOptions {
+    /// `hostaddr`: numeric addresses to use instead of looking up the hosts.
+    /// Empty entries fall back to the corresponding host name.
+    pub hostaddr: Option<Vec<Option<IpAddr>>>,
+    /// `passfile`: the password file to use instead of `~/.pgpass`.
+    pub passfile: Option<PathBuf>,
+    /// `require_auth`: the authentication methods the server may request.
+    pub require_auth: Option<String>,
+    /// `channel_binding`.
+    pub channel_binding: Option<ChannelBinding>,
+    /// `connect_timeout`: how long to wait for each connection attempt. Zero
+    /// or a negative number of seconds means to wait indefinitely, which is
+    /// represented as `None`.
+    pub connect_timeout: Option<Duration>,
+    /// `client_encoding`.
+    pub client_encoding: Option<String>,
+    /// `options`: command-line options sent to the server at startup.
+    pub options: Option<String>,
+    /// `application_name`.
+    pub application_name: Option<String>,
+    /// `fallback_application_name`.
+    pub fallback_application_name: Option<String>,
+    /// `keepalives`: whether to use TCP keepalives.
+    pub keepalives: Option<bool>,
+    /// `keepalives_idle`.
+    pub keepalives_idle: Option<Duration>,
+    /// `keepalives_interval`.
+    pub keepalives_interval: Option<Duration>,
+    /// `keepalives_count`.
+    pub keepalives_count: Option<u32>,
+    /// `tcp_user_timeout`.
+    pub tcp_user_timeout: Option<Duration>,
+    /// `replication`.
+    pub replication: Option<Replication>,
+    /// `gssencmode`.
+    pub gssencmode: Option<GssEncMode>,
+    /// `sslmode`.
+    pub sslmode: Option<SslMode>,
+    /// `sslnegotiation`.
+    pub sslnegotiation: Option<SslNegotiation>,
+    /// `sslcompression`.
+    pub sslcompression: Option<bool>,
+    /// `sslcert`.
+    pub sslcert: Option<PathBuf>,
+    /// `sslkey`.
+    pub sslkey: Option<PathBuf>,
+    /// `sslkeylogfile`.
+    pub sslkeylogfile: Option<PathBuf>,
+    /// `sslpassword`.
+    pub sslpassword: Option<Secret>,
+    /// `sslcertmode`.
+    pub sslcertmode: Option<SslCertMode>,
+    /// `sslrootcert`: a file path, or `system` to use the system's trusted CAs.
+    pub sslrootcert: Option<PathBuf>,
+    /// `sslcrl`.
+    pub sslcrl: Option<PathBuf>,
+    /// `sslcrldir`.
+    pub sslcrldir: Option<PathBuf>,
+    /// `sslsni`.
+    pub sslsni: Option<bool>,
+    /// `requirepeer`.
+    pub requirepeer: Option<String>,
+    /// `ssl_min_protocol_version`.
+    pub ssl_min_protocol_version: Option<SslProtocolVersion>,
+    /// `ssl_max_protocol_version`.
+    pub ssl_max_protocol_version: Option<SslProtocolVersion>,
+    /// `min_protocol_version`.
+    pub min_protocol_version: Option<String>,
+    /// `max_protocol_version`.
+    pub max_protocol_version: Option<String>,
+    /// `krbsrvname`.
+    pub krbsrvname: Option<String>,
+    /// `gsslib`.
+    pub gsslib: Option<String>,
+    /// `gssdelegation`.
+    pub gssdelegation: Option<bool>,
+    /// `service`: a service name to look up in `pg_service.conf`.
+    pub service: Option<String>,
+    /// `target_session_attrs`.
+    pub target_session_attrs: Option<TargetSessionAttrs>,
+    /// `load_balance_hosts`.
+    pub load_balance_hosts: Option<LoadBalanceHosts>,
+    /// `oauth_issuer`.
+    pub oauth_issuer: Option<String>,
+    /// `oauth_client_id`.
+    pub oauth_client_id: Option<String>,
+    /// `oauth_client_secret`.
+    pub oauth_client_secret: Option<Secret>,
+    /// `oauth_scope`.
+    pub oauth_scope: Option<String>,
+}
+
+impl ConnectionOptions {
+    /// Read the libpq connection parameters out of a list of query params.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error for the first parameter whose value libpq wouldn't
+    /// accept.
+    pub fn from_parameters(parameters: &[Parameter]) -> Result<Self, InvalidOption> {
+        let mut out = ConnectionOptions::default();
+
+        for Parameter { keyword, value } in parameters {
+            let kw = keyword.as_str();
+            let v = value.as_str();
+
+            match kw {
+                "hostaddr" => {
+                    out.hostaddr = Some(
+                        v.split(',')
+                            .map(|ip| match ip {
+                                "" => Ok(None),
+                                ip => ip.parse().map(Some).map_err(|_| {
+                                    InvalidOption::new(kw, v, "a list of IP addresses")
+                                }),
+                            })
+                            .collect::<Result<_, _>>()?,
+                    );
+                }
+                "passfile" => out.passfile = Some(v.into()),
+                "require_auth" => out.require_auth = Some(v.into()),
+                "channel_binding" => out.channel_binding = Some(v.parse()?),
+                "connect_timeout" => {
+                    let secs = parse_int(kw, v)?;
+                    out.connect_timeout =
+                        (secs > 0).then(|| Duration::from_secs(secs.unsigned_abs()));
+                }
+                "client_encoding" => out.client_encoding = Some(v.into()),
+                "options" => out.options = Some(v.into()),
+                "application_name" => out.application_name = Some(v.into()),
+                "fallback_application_name" => out.fallback_application_name = Some(v.into()),
+                "keepalives" => out.keepalives = Some(parse_int(kw, v)? != 0),
+                "keepalives_idle" => out.keepalives_idle = Some(parse_secs(kw, v)?),
+                "keepalives_interval" => out.keepalives_interval = Some(parse_secs(kw, v)?),
+                "keepalives_count" => {
+                    out.keepalives_count = Some(
+                        v.parse()
+                            .map_err(|_| InvalidOption::new(kw, v, "a non-negative integer"))?,
+                    );
+                }
+                "tcp_user_timeout" => {
+                    out.tcp_user_timeout =
+                        Some(Duration::from_millis(v.parse().map_err(|_| {
+                            InvalidOption::new(kw, v, "a non-negative number of milliseconds")
+                        })?));
+                }
+                "replication" => out.replication = Some(v.parse()?),
+                "gssencmode" => out.gssencmode = Some(v.parse()?),
+                "sslmode" => out.sslmode = Some(v.parse()?),
+                "sslnegotiation" => out.sslnegotiation = Some(v.parse()?),
+                "sslcompression" => out.sslcompression = Some(parse_int(kw, v)? != 0),
+                "sslcert" => out.sslcert = Some(v.into()),
+                "sslkey" => out.sslkey = Some(v.into()),
+                "sslkeylogfile" => out.sslkeylogfile = Some(v.into()),
+                "sslpassword" => out.sslpassword = Some(v.into()),
+                "sslcertmode" => out.sslcertmode = Some(v.parse()?),
+                "sslrootcert" => out.sslrootcert = Some(v.into()),
+                "sslcrl" => out.sslcrl = Some(v.into()),
+                "sslcrldir" => out.sslcrldir = Some(v.into()),
+                "sslsni" => out.sslsni = Some(parse_int(kw, v)? != 0),
+                "requirepeer" => out.requirepeer = Some(v.into()),
+                "ssl_min_protocol_version" => out.ssl_min_protocol_version = Some(v.parse()?),
+                "ssl_max_protocol_version" => {
+                    out.ssl_max_protocol_version =
+                        Some(v.parse().map_err(|e: InvalidOption| InvalidOption {
+                            keyword: kw.to_string(),
+                            ..e
+                        })?);
+                }
+                "min_protocol_version" => out.min_protocol_version = Some(v.into()),
+                "max_protocol_version" => out.max_protocol_version = Some(v.into()),
+                "krbsrvname" => out.krbsrvname = Some(v.into()),
+                "gsslib" => out.gsslib = Some(v.into()),
+                "gssdelegation" => out.gssdelegation = Some(parse_int(kw, v)? != 0),
+                "service" => out.service = Some(v.into()),
+                "target_session_attrs" => out.target_session_attrs = Some(v.parse()?),
+                "load_balance_hosts" => out.load_balance_hosts = Some(v.parse()?),
+                "oauth_issuer" => out.oauth_issuer = Some(v.into()),
+                "oauth_client_id" => out.oauth_client_id = Some(v.into()),
+                "oauth_client_secret" => out.oauth_client_secret = Some(v.into()),
+                "oauth_scope" => out.oauth_scope = Some(v.into()),
+                _ => {}
+            }
+        }
+
+        Ok(out)
+    }
+}
+
+/// Parse an integer the way libpq does, allowing surrounding whitespace.
+fn parse_int(keyword: &str, value: &str) -> Result<i64, InvalidOption> {
+    value
+        .trim()
+        .parse()
+        .map_err(|_| InvalidOption::new(keyword, value, "an integer"))
+}
+
+/// Parse a non-negative number of seconds.
+fn parse_secs(keyword: &str, value: &str) -> Result<Duration, InvalidOption> {
+    value
+        .trim()
+        .parse()
+        .map(Duration::from_secs)
+        .map_err(|_| InvalidOption::new(keyword, value, "a non-negative number of seconds"))
+}
+
+/// Parse a boolean in any of the spellings the server accepts.
+fn parse_bool(keyword: &str, value: &str) -> Result<bool, InvalidOption> {
+    match value.to_ascii_lowercase().as_str() {
+        "1" | "t" | "true" | "on" | "y" | "yes" => Ok(true),
+        "0" | "f" | "false" | "off" | "n" | "no" => Ok(false),
+        _ => Err(InvalidOption::new(keyword, value, "a boolean")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn params(pairs: &[(&str, &str)]) -> Vec<Parameter> {
+        pairs
+            .iter()
+            .map(|(keyword, value)| Parameter {
+                keyword: (*keyword).to_string(),
+                value: (*value).to_string(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_from_parameters() {
+        assert_eq!(
+            ConnectionOptions::from_parameters(&params(&[
+                ("sslmode", "prefer"),
+                ("sslmode", "verify-full"),
+                ("connect_timeout", "10"),
+                ("keepalives", "1"),
+                ("keepalives_idle", "60"),
+                ("tcp_user_timeout", "1500"),
+                ("target_session_attrs", "prefer-standby"),
+                ("load_balance_hosts", "random"),
+                ("gssencmode", "disable"),
+                ("channel_binding", "require"),
+                ("replication", "database"),
+                ("ssl_max_protocol_version", "TLSv1.3"),
+                ("hostaddr", "10.0.0.1,,::1"),
+                ("application_name", "api"),
+                ("not_a_libpq_option", "ignored"),
+            ]))
+            .unwrap(),
+            ConnectionOptions {
+                sslmode: Some(SslMode::VerifyFull),
+                connect_timeout: Some(Duration::from_secs(10)),
+                keepalives: Some(true),
+                keepalives_idle: Some(Duration::from_secs(60)),
+                tcp_user_timeout: Some(Duration::from_millis(1500)),
+                target_session_attrs: Some(TargetSessionAttrs::PreferStandby),
+                load_balance_hosts: Some(LoadBalanceHosts::Random),
+                gssencmode: Some(GssEncMode::Disable),
+                channel_binding: Some(ChannelBinding::Require),
+                replication: Some(Replication::Database),
+                ssl_max_protocol_version: Some(SslProtocolVersion::Tlsv1_3),
+                hostaddr: Some(vec![
+                    Some("10.0.0.1".parse().unwrap()),
+                    None,
+                    Some("::1".parse().unwrap()),
+                ]),
+                application_name: Some("api".into()),
+                ..ConnectionOptions::default()
+            }
+        );
+
+        // Secrets are masked, as in a connection string.
+        let options = ConnectionOptions::from_parameters(&params(&[
+            ("sslpassword", "hunter2"),
+            ("oauth_client_secret", "hunter3"),
+        ]))
+        .unwrap();
+        assert_eq!(
+            options.sslpassword.as_ref().map(Secret::expose),
+            Some("hunter2")
+        );
+        let debug = format!("{options:?}");
+        assert!(!debug.contains("hunter"), "debug: {debug}");
+
+        assert_eq!(
+            ConnectionOptions::from_parameters(&params(&[("connect_timeout", "0")]))
+                .unwrap()
+                .connect_timeout,
+            None
+        );
+    }
+
+    #[test]
+    fn test_from_parameters_errors() {
+        for (keyword, value, expected) in [
+            (
+                "sslmode",
+                "verify",
+                "one of disable, allow, prefer, require, verify-ca, verify-full",
+            ),
+            ("connect_timeout", "ten", "an integer"),
+            ("keepalives_idle", "-1", "a non-negative number of seconds"),
+            ("replication", "maybe", "a boolean"),
+            (
+                "ssl_max_protocol_version",
+                "SSLv3",
+                "one of TLSv1, TLSv1.1, TLSv1.2, TLSv1.3",
+            ),
+            ("hostaddr", "myhost", "a list of IP addresses"),
+        ] {
+            assert_eq!(
+                ConnectionOptions::from_parameters(&params(&[(keyword, value)])),
+                Err(InvalidOption {
+                    keyword: keyword.into(),
+                    value: value.into(),
+                    expected: expected.into(),
+                }),
+                "keyword: {keyword:?}; value: {value:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_keyword_enum() {
+        for mode in [
+            SslMode::Disable,
+            SslMode::Allow,
+            SslMode::Prefer,
+            SslMode::Require,
+            SslMode::VerifyCa,
+            SslMode::VerifyFull,
+        ] {
+            assert_eq!(mode.to_string().parse::<SslMode>(), Ok(mode));
+        }
+
+        for (input, expect) in [
+            ("on", Replication::Physical),
+            ("yes", Replication::Physical),
+            ("0", Replication::Off),
+            ("database", Replication::Database),
+        ] {
+            assert_eq!(input.parse(), Ok(expect), "input: {input:?}");
+        }
+    }
+}
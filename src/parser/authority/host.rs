@@ -2,126 +2,258 @@
 If you'd like to go insane, go read [https://paquier.xyz/postgresql-2/postgres-10-multi-host-connstr](https://paquier.xyz/postgresql-2/postgres-10-multi-host-connstr).
 */
 
-use super::Res;
-use anyhow::anyhow;
+use super::{Error, Res};
+use crate::{
+    ast::{HostSpec, Spanned},
+    parser::{consumed, query::urlencoded1, spanned},
+    percent, ParseError,
+};
 use nom::{
     branch::alt,
     bytes::complete::tag,
     character::complete::{alphanumeric1, digit1, hex_digit1, one_of, u16},
-    combinator::{map, map_res, opt, recognize},
-    multi::{count, many0, many1, separated_list0},
-    sequence::{delimited, pair, preceded, terminated, tuple},
+    combinator::{all_consuming, map, map_opt, map_res, opt, recognize},
+    error::VerboseError,
+    multi::{many0, many1_count},
+    sequence::{delimited, pair, preceded, tuple},
     Finish,
 };
 use std::{
+    borrow::Cow,
     fmt::Display,
     net::{IpAddr, Ipv4Addr, Ipv6Addr},
-    path::PathBuf,
+    path::{Path, PathBuf},
     str::FromStr,
 };
 
-#[derive(Clone, Debug, Default, PartialEq, Eq)]
-pub(crate) struct HostSpec {
-    pub host: Option<Host>,
-    pub port: Option<u16>,
-}
-
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub enum Host {
     Path(PathBuf),
     Name(String),
     Ip(IpAddr),
+    /// An IPv6 address with a zone identifier, e.g. `fe80::1%eth0`.
+    ScopedIp(Ipv6Addr, String),
 }
 
 impl Display for Host {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Host::Path(path) => {
-                write!(f, "{}", path.to_str().unwrap_or("<invalid>"))
+                write!(
+                    f,
+                    "{}",
+                    percent::encode(path.to_str().unwrap_or("<invalid>"), "")
+                )
             }
-            Host::Name(name) => write!(f, "{name}"),
+            Host::Name(name) => write!(f, "{}", percent::encode(name, "")),
             Host::Ip(IpAddr::V4(ip)) => write!(f, "{ip}"),
             Host::Ip(IpAddr::V6(ip)) => write!(f, "[{ip}]"),
+            Host::ScopedIp(ip, zone) => write!(f, "[{ip}%25{}]", percent::encode(zone, "")),
+        }
+    }
+}
+
+impl Host {
+    /// Interpret a `host` value given as a query parameter or keyword/value
+    /// pair. See [`HostRef::from_value`].
+    pub(crate) fn from_value(s: &str) -> Option<Self> {
+        HostRef::from_value(s).map(HostRef::into_owned)
+    }
+
+    /// Format the host the way [`Host::from_value`] reads it back.
+    pub(crate) fn to_value(&self) -> String {
+        match self {
+            Host::Path(path) => path.to_string_lossy().into_owned(),
+            Host::Name(name) => name.clone(),
+            Host::Ip(ip) => ip.to_string(),
+            Host::ScopedIp(ip, zone) => format!("{ip}%{zone}"),
         }
     }
 }
 
 impl FromStr for Host {
-    type Err = anyhow::Error;
+    type Err = ParseError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        host(s)
+        all_consuming(host::<VerboseError<&str>>)(s)
             .finish()
-            .map(|(_, host)| host)
-            .map_err(|e| anyhow!(e.to_string()))
+            .map(|(_, host)| host.into_owned())
+            .map_err(|e| ParseError::Host {
+                input: s.to_string(),
+                offset: e.errors.first().map_or(0, |(rem, _)| s.len() - rem.len()),
+            })
+    }
+}
+
+/// A [`Host`] borrowed from the input it was parsed from. Names and socket
+/// directories are only copied if they had to be decoded.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum HostRef<'a> {
+    Path(Cow<'a, Path>),
+    Name(Cow<'a, str>),
+    Ip(IpAddr),
+    /// An IPv6 address with a zone identifier, e.g. `fe80::1%eth0`.
+    ScopedIp(Ipv6Addr, Cow<'a, str>),
+}
+
+impl<'a> HostRef<'a> {
+    /// Interpret a `host` value given as a query parameter or keyword/value
+    /// pair, where socket directories start with `/` and IPv6 addresses are
+    /// written without brackets. Returns `None` for an empty value.
+    pub(crate) fn from_value(s: &'a str) -> Option<Self> {
+        if s.is_empty() {
+            return None;
+        }
+
+        if s.starts_with('/') {
+            return Some(HostRef::Path(Cow::Borrowed(Path::new(s))));
+        }
+
+        if let Some((ip, zone)) = s.split_once('%') {
+            if let Ok(ip) = Ipv6Addr::from_str(ip) {
+                return Some(HostRef::ScopedIp(ip, Cow::Borrowed(zone)));
+            }
+        }
+
+        Some(IpAddr::from_str(s).map_or(HostRef::Name(Cow::Borrowed(s)), HostRef::Ip))
+    }
+
+    /// Interpret a name, which is a socket directory if it starts with `/`.
+    fn from_name(name: Cow<'a, str>) -> Self {
+        if !name.starts_with('/') {
+            return HostRef::Name(name);
+        }
+
+        HostRef::Path(match name {
+            Cow::Borrowed(name) => Cow::Borrowed(Path::new(name)),
+            Cow::Owned(name) => Cow::Owned(PathBuf::from(name)),
+        })
+    }
+
+    /// Copy the host out of the input.
+    #[must_use]
+    pub fn into_owned(self) -> Host {
+        match self {
+            HostRef::Path(path) => Host::Path(path.into_owned()),
+            HostRef::Name(name) => Host::Name(name.into_owned()),
+            HostRef::Ip(ip) => Host::Ip(ip),
+            HostRef::ScopedIp(ip, zone) => Host::ScopedIp(ip, zone.into_owned()),
+        }
+    }
+
+    /// Copy the host out of the input, keeping it a `HostRef`.
+    pub(crate) fn detach(self) -> HostRef<'static> {
+        match self {
+            HostRef::Path(path) => HostRef::Path(Cow::Owned(path.into_owned())),
+            HostRef::Name(name) => HostRef::Name(Cow::Owned(name.into_owned())),
+            HostRef::Ip(ip) => HostRef::Ip(ip),
+            HostRef::ScopedIp(ip, zone) => HostRef::ScopedIp(ip, Cow::Owned(zone.into_owned())),
+        }
+    }
+}
+
+impl From<HostRef<'_>> for Host {
+    fn from(host: HostRef<'_>) -> Self {
+        host.into_owned()
     }
 }
 
 /// Get a list of host:port pairs.
-pub(crate) fn hostspecs(i: &str) -> Res<&str, Vec<HostSpec>> {
+pub(crate) fn hostspecs<'a, E: Error<'a>>(i: &'a str) -> Res<'a, Vec<HostSpec<'a>>, E> {
     // We deconstruct the result a bit to remove any pairs that are both None.
     many0(hostspec)(i)
 }
 
-/// Get a list of host:port pairs.
-pub(crate) fn hostspec(i: &str) -> Res<&str, HostSpec> {
-    // We deconstruct the result a bit to remove any pairs that are both None.
-    terminated(
-        alt((
-            map(pair(host, port), |(host, port)| HostSpec {
-                host: Some(host),
-                port: Some(port),
-            }),
-            map(pair(host, opt(port)), |(host, port)| HostSpec {
-                host: Some(host),
-                port,
-            }),
-            map(pair(opt(host), port), |(host, port)| HostSpec {
-                host: host.map(std::convert::Into::into),
-                port: Some(port),
-            }),
-        )),
-        opt(tag(",")),
-    )(i)
+/// Get a host:port pair, and the comma after it if there is one.
+pub(crate) fn hostspec<'a, E: Error<'a>>(i: &'a str) -> Res<'a, HostSpec<'a>, E> {
+    let (after, (host, port)) = alt((
+        map(pair(spanned(host), port), |(host, port)| {
+            (Some(host), Some(port))
+        }),
+        pair(map(spanned(host), Some), opt(port)),
+        map(pair(opt(spanned(host)), port), |(host, port)| {
+            (host, Some(port))
+        }),
+    ))(i)?;
+    let (rem, _) = opt(tag(","))(after)?;
+
+    Ok((
+        rem,
+        HostSpec {
+            span: consumed(i, after),
+            host,
+            port,
+        },
+    ))
 }
 
 /// Parse the `port` component of a URI.
-fn port(i: &str) -> Res<&str, u16> {
-    preceded(tag(":"), u16)(i)
+fn port<'a, E: Error<'a>>(i: &'a str) -> Res<'a, Spanned<u16>, E> {
+    preceded(tag(":"), spanned(u16))(i)
 }
 
-/// Parse the `host` component of a URI.
-fn host(i: &str) -> Res<&str, Host> {
+/// Parse the `host` component of a URI. Names are percent-decoded, and a name
+/// that decodes to an absolute path (e.g. `%2Fvar%2Frun%2Fpostgresql`) is a
+/// Unix socket directory.
+fn host<'a, E: Error<'a>>(i: &'a str) -> Res<'a, HostRef<'a>, E> {
     alt((
-        map(ipv4, |ip| Host::Ip(IpAddr::V4(ip))),
-        map(ipv6, |ip| Host::Ip(IpAddr::V6(ip))),
+        map(ipv4, |ip| HostRef::Ip(IpAddr::V4(ip))),
+        map(ipv6, |(ip, zone)| match zone {
+            Some(zone) => HostRef::ScopedIp(ip, zone),
+            None => HostRef::Ip(IpAddr::V6(ip)),
+        }),
         map(
-            recognize(many1(alt((alphanumeric1, recognize(one_of("._-")))))),
-            |s: &str| Host::Name(s.to_string()),
+            map_opt(
+                recognize(many1_count(alt((
+                    alphanumeric1,
+                    recognize(one_of("._-~")),
+                    recognize(urlencoded1),
+                )))),
+                percent::decode,
+            ),
+            HostRef::from_name,
         ),
     ))(i)
 }
 
-/// Recognize an ipv6 address.
-fn ipv6(i: &str) -> Res<&str, Ipv6Addr> {
+/// Recognize a bracketed ipv6 address in any of its RFC 4291 textual forms,
+/// with an optional RFC 6874 zone identifier (e.g. `[fe80::1%25eth0]`).
+fn ipv6<'a, E: Error<'a>>(i: &'a str) -> Res<'a, (Ipv6Addr, Option<Cow<'a, str>>), E> {
     delimited(
         tag("["),
-        map_res(
-            recognize(tuple((
-                separated_list0(tag(":"), hex_digit1),
-                tag("::"),
-                hex_digit1,
-            ))),
-            Ipv6Addr::from_str,
+        pair(
+            map_res(
+                recognize(many1_count(alt((hex_digit1, tag(":"), tag("."))))),
+                Ipv6Addr::from_str,
+            ),
+            opt(preceded(
+                tag("%25"),
+                map_opt(
+                    recognize(many1_count(alt((
+                        alphanumeric1,
+                        recognize(one_of("._-~")),
+                        recognize(urlencoded1),
+                    )))),
+                    percent::decode,
+                ),
+            )),
         ),
         tag("]"),
     )(i)
 }
 
 /// Recognize an ipv4 address.
-fn ipv4(i: &str) -> Res<&str, Ipv4Addr> {
+fn ipv4<'a, E: Error<'a>>(i: &'a str) -> Res<'a, Ipv4Addr, E> {
     map_res(
-        recognize(pair(count(pair(digit1, tag(".")), 3), digit1)),
+        recognize(tuple((
+            digit1,
+            tag("."),
+            digit1,
+            tag("."),
+            digit1,
+            tag("."),
+            digit1,
+        ))),
         Ipv4Addr::from_str,
     )(i)
 }
@@ -129,14 +261,16 @@ fn ipv4(i: &str) -> Res<&str, Ipv4Addr> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::parser::{no_span, unspanned, Verbose};
 
     #[test]
+    #[allow(clippy::useless_vec)]
     fn test_port() {
         // Good cases.
-        for (input, rem, output) in vec![(":123", "", 123)] {
+        for (input, rem, output) in vec![(":123", "", 123), (":5432/db", "/db", 5432)] {
             assert_eq!(
-                port(input).unwrap(),
-                (rem, output),
+                port::<Verbose>(input).map(|(rem, port)| (rem, port.value)),
+                Ok((rem, output)),
                 "input: {:?}; rem: {:?}",
                 input,
                 rem
@@ -144,11 +278,12 @@ mod tests {
         }
 
         for input in vec![",123", ",:123", ":port"] {
-            assert!(port(input).is_err());
+            assert!(port::<Verbose>(input).is_err());
         }
     }
 
     #[test]
+    #[allow(clippy::useless_vec)]
     fn test_host() {
         // Good cases.
         for (input, rem, output) in vec![
@@ -174,42 +309,90 @@ mod tests {
                 "/database",
                 Host::Ip(IpAddr::from_str("2001:db8::1234").unwrap()),
             ),
+            (
+                "%2Fvar%2Frun%2Fpostgresql/db",
+                "/db",
+                Host::Path(PathBuf::from("/var/run/postgresql")),
+            ),
+            ("%2ftmp:5432", ":5432", Host::Path(PathBuf::from("/tmp"))),
+            ("my%2Dhost", "", Host::Name("my-host".into())),
         ] {
             assert_eq!(
-                host(input),
+                host::<Verbose>(input).map(|(rem, host)| (rem, host.into_owned())),
                 Ok((rem, output)),
                 "input: {input:?}; rem: {rem:?}",
             );
         }
 
         // Bad cases.
-        for input in [",host", "/db"] {
-            assert!(host(input).is_err());
+        for input in [",host", "/db", "%zz", "%C3"] {
+            assert!(host::<Verbose>(input).is_err(), "input: {input:?}");
         }
+
+        // Names are only copied if they had to be decoded.
+        assert!(matches!(
+            host::<Verbose>("myhost:5432"),
+            Ok((":5432", HostRef::Name(Cow::Borrowed("myhost"))))
+        ));
+        assert!(matches!(
+            host::<Verbose>("%2Ftmp"),
+            Ok(("", HostRef::Path(Cow::Owned(_))))
+        ));
     }
 
     #[test]
     fn test_ipv6() {
         // Good cases.
-        for (input, rem, output) in [(
-            "[2001:db8::1234]",
-            "",
-            Ipv6Addr::from_str("2001:db8::1234").unwrap(),
-        )] {
+        for (input, rem, output, zone) in [
+            ("[2001:db8::1234]", "", "2001:db8::1234", None),
+            ("[2001:db8::1234]:5432", ":5432", "2001:db8::1234", None),
+            ("[::1]", "", "::1", None),
+            ("[::]", "", "::", None),
+            ("[2001:db8:0:0:0:0:0:1]/db", "/db", "2001:db8::1", None),
+            ("[2001:DB8::A:B]", "", "2001:db8::a:b", None),
+            ("[1::]", "", "1::", None),
+            ("[::ffff:10.0.0.1]", "", "::ffff:10.0.0.1", None),
+            ("[64:ff9b::192.0.2.33]", "", "64:ff9b::c000:221", None),
+            ("[fe80::1%25eth0]:5432", ":5432", "fe80::1", Some("eth0")),
+            ("[fe80::1%25en0.100]", "", "fe80::1", Some("en0.100")),
+            ("[fe80::1%25%65th0]", "", "fe80::1", Some("eth0")),
+        ] {
             assert_eq!(
-                ipv6(input),
-                Ok((rem, output)),
+                ipv6::<Verbose>(input),
+                Ok((
+                    rem,
+                    (Ipv6Addr::from_str(output).unwrap(), zone.map(Cow::Borrowed))
+                )),
                 "input: {input:?}; rem: {rem:?}",
             );
         }
+
+        // Bad cases.
+        for input in [
+            "[]",
+            "[1::2::3]",
+            "[2001:db8:0:0:0:0:0:0:1]",
+            "[12345::]",
+            "[::1",
+            "::1",
+            "[fe80::1%eth0]",
+            "[fe80::1%25]",
+            "[::ffff:10.0.0]",
+        ] {
+            assert!(ipv6::<Verbose>(input).is_err(), "input: {input:?}");
+        }
     }
 
     #[test]
+    #[allow(clippy::useless_vec)]
     fn test_ipv4() {
         // Good cases.
-        for (input, rem, output) in vec![("192.168.0.1", "", Ipv4Addr::new(192, 168, 0, 1))] {
+        for (input, rem, output) in vec![
+            ("192.168.0.1", "", Ipv4Addr::new(192, 168, 0, 1)),
+            ("10.0.0.1:5432", ":5432", Ipv4Addr::new(10, 0, 0, 1)),
+        ] {
             assert_eq!(
-                ipv4(input).unwrap(),
+                ipv4::<Verbose>(input).unwrap(),
                 (rem, output),
                 "input: {:?}; rem: {:?}",
                 input,
@@ -218,35 +401,121 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_from_value() {
+        for (input, output) in [
+            ("myhost", Host::Name("myhost".into())),
+            ("/tmp", Host::Path(PathBuf::from("/tmp"))),
+            ("10.0.0.1", Host::Ip(IpAddr::from_str("10.0.0.1").unwrap())),
+            ("::1", Host::Ip(IpAddr::from_str("::1").unwrap())),
+        ] {
+            assert_eq!(Host::from_value(input).unwrap(), output, "input: {input:?}");
+        }
+
+        assert!(Host::from_value("").is_none());
+    }
+
+    #[test]
+    fn test_display() {
+        for (input, expect) in [
+            (Host::Name("myhost".into()), "myhost"),
+            (
+                Host::Path(PathBuf::from("/var/run/postgresql")),
+                "%2Fvar%2Frun%2Fpostgresql",
+            ),
+            (
+                Host::Ip(IpAddr::from_str("2001:db8::1234").unwrap()),
+                "[2001:db8::1234]",
+            ),
+            (
+                Host::ScopedIp(Ipv6Addr::from_str("fe80::1").unwrap(), "eth0".into()),
+                "[fe80::1%25eth0]",
+            ),
+            (
+                Host::Ip(IpAddr::from_str("::ffff:10.0.0.1").unwrap()),
+                "[::ffff:10.0.0.1]",
+            ),
+        ] {
+            assert_eq!(input.to_string(), expect);
+            assert_eq!(expect.parse::<Host>().unwrap(), input);
+        }
+    }
+
+    #[test]
+    fn test_from_str_errors() {
+        for (input, offset) in [("db1:5432", 3), ("db1/x", 3), ("[::1]x", 5), ("", 0)] {
+            assert_eq!(
+                input.parse::<Host>(),
+                Err(ParseError::Host {
+                    input: input.into(),
+                    offset,
+                }),
+                "input: {input:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_to_value() {
+        for input in [
+            "myhost",
+            "/tmp",
+            "10.0.0.1",
+            "::1",
+            "2001:db8::1234",
+            "fe80::1%eth0",
+        ] {
+            assert_eq!(
+                Host::from_value(input).unwrap().to_value(),
+                input,
+                "input: {input:?}"
+            );
+        }
+    }
+
     macro_rules! host {
         ($s:expr) => {
-            Some(host($s).unwrap().1)
+            Some(no_span(host::<Verbose>($s).unwrap().1))
         };
     }
 
     #[test]
+    #[allow(clippy::useless_vec)]
     fn test_hostspec() {
         // Good cases.
-        for (input, rem, output) in vec![(
-            "myhost:123",
-            "",
-            HostSpec {
-                host: host!("myhost"),
-                port: Some(123),
-            },
-        )] {
+        for (input, rem, output) in vec![
+            (
+                "myhost:123",
+                "",
+                HostSpec {
+                    span: 0..0,
+                    host: host!("myhost"),
+                    port: Some(no_span(123)),
+                },
+            ),
+            (
+                "myhost,otherhost",
+                "otherhost",
+                HostSpec {
+                    span: 0..0,
+                    host: host!("myhost"),
+                    port: None,
+                },
+            ),
+        ] {
             assert_eq!(
-                hostspec(input).unwrap(),
+                unspanned(hostspec::<Verbose>(input)).unwrap(),
                 (rem, output),
                 "input: {input:?}; rem: {rem:?}",
             );
         }
 
         for input in &["", ",", ",host:123"] {
-            assert!(hostspec(input).is_err());
+            assert!(hostspec::<Verbose>(input).is_err());
         }
     }
     #[test]
+    #[allow(clippy::useless_vec)]
     fn test_hostspecs() {
         // Good cases.
         for (input, rem, output) in vec![
@@ -257,8 +526,9 @@ mod tests {
                 "myhost:123",
                 "",
                 vec![HostSpec {
+                    span: 0..0,
                     host: host!("myhost"),
-                    port: Some(123),
+                    port: Some(no_span(123)),
                 }],
             ),
             (
@@ -266,18 +536,20 @@ mod tests {
                 "",
                 vec![
                     HostSpec {
+                        span: 0..0,
                         host: host!("myhost"),
-                        port: Some(123),
+                        port: Some(no_span(123)),
                     },
                     HostSpec {
+                        span: 0..0,
                         host: host!("secondhost"),
-                        port: Some(65535),
+                        port: Some(no_span(65535)),
                     },
                 ],
             ),
         ] {
             assert_eq!(
-                hostspecs(input).unwrap(),
+                unspanned(hostspecs::<Verbose>(input)).unwrap(),
                 (rem, output),
                 "input: {input:?}; rem: {rem:?}",
             );
@@ -3,34 +3,33 @@ pub(crate) mod host;
 mod tests;
 pub(crate) mod userinfo;
 
-use super::Res;
-use host::{hostspecs, HostSpec};
+use super::{consumed, Error, Res};
+use crate::ast::Authority;
+use host::hostspecs;
 use nom::{
     bytes::complete::tag,
-    combinator::{fail, map, opt},
+    combinator::{fail, opt},
     sequence::{terminated, tuple},
 };
-use userinfo::{userinfo, UserSpec};
-
-#[derive(Debug, Default, PartialEq, Eq)]
-pub(crate) struct Authority {
-    pub userspec: Option<UserSpec>,
-    pub hostspec: Vec<HostSpec>,
-}
+use userinfo::userinfo;
 
 /// Parse the `authority` from the connection string. Unlike a standard URI,
 /// this will accept a comma-separated list of host:port pairs.
-pub(crate) fn authority(i: &str) -> Res<&str, Authority> {
+pub(crate) fn authority<'a, E: Error<'a>>(i: &'a str) -> Res<'a, Authority<'a>, E> {
     // We don't want to return an empty Vec of host:port pairs if there aren't any,
-    // so we'll match on the result to replace an empty vec with None.
+    // so we'll match on the result to fail if there's nothing here.
     //
     // TODO: Do this better directly in nom.
-    match map(
-        tuple((opt(terminated(userinfo, tag("@"))), hostspecs)),
-        |(userspec, hostspec)| Authority { userspec, hostspec },
-    )(i)
-    {
-        Ok((rem, auth)) if auth.userspec.is_none() && auth.hostspec.is_empty() => fail(rem),
-        u => u,
+    match tuple((opt(terminated(userinfo, tag("@"))), hostspecs))(i) {
+        Ok((rem, (None, hostspec))) if hostspec.is_empty() => fail(rem),
+        Ok((rem, (userspec, hostspec))) => Ok((
+            rem,
+            Authority {
+                span: consumed(i, rem),
+                userspec,
+                hostspec,
+            },
+        )),
+        Err(e) => Err(e),
     }
 }
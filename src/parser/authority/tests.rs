@@ -1,7 +1,13 @@
 use super::*;
+use crate::{
+    ast::{HostSpec, UserSpec},
+    parser::{no_span, unspanned, Verbose},
+    HostRef,
+};
+use std::path::Path;
 
 #[test]
-#[allow(clippy::too_many_lines)]
+#[allow(clippy::too_many_lines, clippy::useless_vec)]
 fn test_authority() {
     // Good cases.
     for (input, rem, output) in vec![
@@ -9,13 +15,16 @@ fn test_authority() {
             "jack:secret@myhost:123",
             "",
             Authority {
+                span: 0..0,
                 userspec: Some(UserSpec {
-                    user: "jack".to_string(),
-                    password: Some("secret".to_string()),
+                    span: 0..0,
+                    user: no_span("jack".into()),
+                    password: Some(no_span("secret".into())),
                 }),
                 hostspec: vec![HostSpec {
-                    host: Some("myhost".parse().unwrap()),
-                    port: Some(123),
+                    span: 0..0,
+                    host: Some(no_span(HostRef::Name("myhost".into()))),
+                    port: Some(no_span(123)),
                 }],
             },
         ),
@@ -23,13 +32,16 @@ fn test_authority() {
             "jack:secret@myhost:123/mydb",
             "/mydb",
             Authority {
+                span: 0..0,
                 userspec: Some(UserSpec {
-                    user: "jack".to_string(),
-                    password: Some("secret".to_string()),
+                    span: 0..0,
+                    user: no_span("jack".into()),
+                    password: Some(no_span("secret".into())),
                 }),
                 hostspec: vec![HostSpec {
-                    host: Some("myhost".parse().unwrap()),
-                    port: Some(123),
+                    span: 0..0,
+                    host: Some(no_span(HostRef::Name("myhost".into()))),
+                    port: Some(no_span(123)),
                 }],
             },
         ),
@@ -37,18 +49,22 @@ fn test_authority() {
             "jack:secret@myhost1:123,myhost2:456/mydb",
             "/mydb",
             Authority {
+                span: 0..0,
                 userspec: Some(UserSpec {
-                    user: "jack".to_string(),
-                    password: Some("secret".to_string()),
+                    span: 0..0,
+                    user: no_span("jack".into()),
+                    password: Some(no_span("secret".into())),
                 }),
                 hostspec: vec![
                     HostSpec {
-                        host: Some("myhost1".parse().unwrap()),
-                        port: Some(123),
+                        span: 0..0,
+                        host: Some(no_span(HostRef::Name("myhost1".into()))),
+                        port: Some(no_span(123)),
                     },
                     HostSpec {
-                        host: Some("myhost2".parse().unwrap()),
-                        port: Some(456),
+                        span: 0..0,
+                        host: Some(no_span(HostRef::Name("myhost2".into()))),
+                        port: Some(no_span(456)),
                     },
                 ],
             },
@@ -57,12 +73,15 @@ fn test_authority() {
             "jack:secret@myhost",
             "",
             Authority {
+                span: 0..0,
                 userspec: Some(UserSpec {
-                    user: "jack".to_string(),
-                    password: Some("secret".to_string()),
+                    span: 0..0,
+                    user: no_span("jack".into()),
+                    password: Some(no_span("secret".into())),
                 }),
                 hostspec: vec![HostSpec {
-                    host: Some("myhost".parse().unwrap()),
+                    span: 0..0,
+                    host: Some(no_span(HostRef::Name("myhost".into()))),
                     port: None,
                 }],
             },
@@ -71,12 +90,15 @@ fn test_authority() {
             "jack@myhost",
             "",
             Authority {
+                span: 0..0,
                 userspec: Some(UserSpec {
-                    user: "jack".to_string(),
+                    span: 0..0,
+                    user: no_span("jack".into()),
                     password: None,
                 }),
                 hostspec: vec![HostSpec {
-                    host: Some("myhost".parse().unwrap()),
+                    span: 0..0,
+                    host: Some(no_span(HostRef::Name("myhost".into()))),
                     port: None,
                 }],
             },
@@ -85,9 +107,11 @@ fn test_authority() {
             "myhost",
             "",
             Authority {
+                span: 0..0,
                 userspec: None,
                 hostspec: vec![HostSpec {
-                    host: Some("myhost".parse().unwrap()),
+                    span: 0..0,
+                    host: Some(no_span(HostRef::Name("myhost".into()))),
                     port: None,
                 }],
             },
@@ -96,18 +120,44 @@ fn test_authority() {
             "jack@/mydb",
             "/mydb",
             Authority {
+                span: 0..0,
                 userspec: Some(UserSpec {
-                    user: "jack".to_string(),
+                    span: 0..0,
+                    user: no_span("jack".into()),
                     password: None,
                 }),
                 hostspec: vec![],
             },
         ),
+        (
+            "myhost:5432,%2Ftmp:5433/mydb",
+            "/mydb",
+            Authority {
+                span: 0..0,
+                userspec: None,
+                hostspec: vec![
+                    HostSpec {
+                        span: 0..0,
+                        host: Some(no_span(HostRef::Name("myhost".into()))),
+                        port: Some(no_span(5432)),
+                    },
+                    HostSpec {
+                        span: 0..0,
+                        host: Some(no_span(HostRef::Path(Path::new("/tmp").into()))),
+                        port: Some(no_span(5433)),
+                    },
+                ],
+            },
+        ),
     ] {
-        assert_eq!(authority(input), Ok((rem, output)), "input: {input:?}",);
+        assert_eq!(
+            unspanned(authority::<Verbose>(input)),
+            Ok((rem, output)),
+            "input: {input:?}",
+        );
     }
 
     for input in ["", ",jack@myhost", "/db"] {
-        assert!(authority(input).is_err(), "input: {input:?}");
+        assert!(authority::<Verbose>(input).is_err(), "input: {input:?}");
     }
 }
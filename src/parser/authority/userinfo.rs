@@ -1,118 +1,170 @@
-use super::Res;
+use super::{Error, Res};
+use crate::{
+    ast::{Spanned, UserSpec},
+    parser::{consumed, query::urlencoded1, spanned},
+    percent,
+};
 use nom::{
     branch::alt,
     bytes::complete::{is_not, tag},
     character::complete::{alphanumeric1, one_of},
-    combinator::{map, opt, recognize},
-    multi::many1,
+    combinator::{map_opt, opt, recognize},
+    multi::many1_count,
     sequence::{preceded, tuple},
 };
+use std::borrow::Cow;
+
+/// Parse the `userinfo` component of a URI, decoding any percent-encoded
+/// characters.
+pub(crate) fn userinfo<'a, E: Error<'a>>(i: &'a str) -> Res<'a, UserSpec<'a>, E> {
+    let (rem, (user, password)) =
+        map_opt(tuple((spanned(user), opt(password))), |(user, password)| {
+            Some((
+                decode(user)?,
+                match password {
+                    Some(password) => Some(decode(password)?),
+                    None => None,
+                },
+            ))
+        })(i)?;
 
-#[derive(Debug, Default, PartialEq, Eq)]
-pub(crate) struct UserSpec {
-    pub user: String,
-    pub password: Option<String>,
+    Ok((
+        rem,
+        UserSpec {
+            span: consumed(i, rem),
+            user,
+            password,
+        },
+    ))
 }
 
-/// Parse the `userinfo` component of a URI.
-pub(crate) fn userinfo(i: &str) -> Res<&str, UserSpec> {
-    map(tuple((user, opt(password))), |(user, password)| UserSpec {
-        user: user.into(),
-        password: password.map(std::convert::Into::into),
-    })(i)
+/// Percent-decode a user or password, keeping its span.
+fn decode(s: Spanned<&str>) -> Option<Spanned<Cow<'_, str>>> {
+    Some(Spanned {
+        value: percent::decode(s.value)?,
+        span: s.span,
+    })
 }
 
 /// Parse the `user` component of the `userinfo component of a uri.
-fn user(i: &str) -> Res<&str, &str> {
-    recognize(many1(alt((alphanumeric1, recognize(one_of("-_"))))))(i)
+fn user<'a, E: Error<'a>>(i: &'a str) -> Res<'a, &'a str, E> {
+    recognize(many1_count(alt((
+        alphanumeric1,
+        recognize(one_of("-_.~")),
+        recognize(urlencoded1),
+    ))))(i)
 }
 
 /// Parse the `password` component of the `userinfo component of a uri.
-fn password(i: &str) -> Res<&str, &str> {
-    preceded(tag(":"), is_not("@"))(i)
+fn password<'a, E: Error<'a>>(i: &'a str) -> Res<'a, Spanned<&'a str>, E> {
+    preceded(tag(":"), spanned(is_not("@")))(i)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::parser::{no_span, unspanned, Verbose};
 
     #[test]
+    #[allow(clippy::useless_vec)]
     fn test_userinfo() {
         // Good cases.
         assert_eq!(
-            userinfo("jack:secret"),
+            unspanned(userinfo::<Verbose>("jack:secret")),
             Ok((
                 "",
                 UserSpec {
-                    user: "jack".to_string(),
-                    password: Some("secret".to_string())
+                    span: 0..0,
+                    user: no_span("jack".into()),
+                    password: Some(no_span("secret".into()))
                 }
             ))
         );
         assert_eq!(
-            userinfo("jack:secret@"),
+            unspanned(userinfo::<Verbose>("jack:secret@")),
             Ok((
                 "@",
                 UserSpec {
-                    user: "jack".to_string(),
-                    password: Some("secret".to_string())
+                    span: 0..0,
+                    user: no_span("jack".into()),
+                    password: Some(no_span("secret".into()))
                 }
             ))
         );
         assert_eq!(
-            userinfo("jack"),
+            unspanned(userinfo::<Verbose>("jack")),
             Ok((
                 "",
                 UserSpec {
-                    user: "jack".to_string(),
+                    span: 0..0,
+                    user: no_span("jack".into()),
                     password: None
                 }
             ))
         );
         assert_eq!(
-            userinfo("jack@"),
+            unspanned(userinfo::<Verbose>("jack@")),
             Ok((
                 "@",
                 UserSpec {
-                    user: "jack".to_string(),
+                    span: 0..0,
+                    user: no_span("jack".into()),
                     password: None
                 }
             ))
         );
         assert_eq!(
-            userinfo("jack:"),
+            unspanned(userinfo::<Verbose>("jack:")),
             Ok((
                 ":",
                 UserSpec {
-                    user: "jack".to_string(),
+                    span: 0..0,
+                    user: no_span("jack".into()),
                     password: None
                 }
             ))
         );
 
-        for input in vec![",jack"] {
-            assert!(userinfo(input).is_err(), "input: {:?}", input);
+        assert_eq!(
+            unspanned(userinfo::<Verbose>("jack%40example.com:p%40ss%3Aword@")),
+            Ok((
+                "@",
+                UserSpec {
+                    span: 0..0,
+                    user: no_span("jack@example.com".into()),
+                    password: Some(no_span("p@ss:word".into()))
+                }
+            ))
+        );
+
+        for input in vec![",jack", "@jack", "jack:bad%zzescape", "jack%C3"] {
+            assert!(userinfo::<Verbose>(input).is_err(), "input: {:?}", input);
         }
     }
 
     #[test]
+    #[allow(clippy::useless_vec)]
     fn test_password() {
         // Good cases.
-        assert_eq!(password(":secret@"), Ok(("@", "secret")));
+        assert_eq!(
+            password::<Verbose>(":secret@").map(|(rem, password)| (rem, password.value)),
+            Ok(("@", "secret"))
+        );
 
         for input in vec!["secret", "secret@", ":@", ":"] {
-            assert!(password(input).is_err(), "input: {}", input);
+            assert!(password::<Verbose>(input).is_err(), "input: {}", input);
         }
     }
 
     #[test]
     fn test_user() {
         // Good cases.
-        assert_eq!(user("jack:"), Ok((":", "jack")));
-        assert_eq!(user("jack"), Ok(("", "jack")));
+        assert_eq!(user::<Verbose>("jack:"), Ok((":", "jack")));
+        assert_eq!(user::<Verbose>("jack"), Ok(("", "jack")));
+        assert_eq!(user::<Verbose>("jack.b%40x:"), Ok((":", "jack.b%40x")));
 
         // Bad cases.
-        assert!(user(":jack").is_err());
-        assert!(user("@jack").is_err());
+        assert!(user::<Verbose>(":jack").is_err());
+        assert!(user::<Verbose>("@jack").is_err());
     }
 }
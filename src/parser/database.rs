@@ -1,15 +1,18 @@
-use super::Res;
+use super::{query::urlencoded1, spanned, Error, Res};
+use crate::{ast::Spanned, percent};
 use nom::{
     branch::alt,
     bytes::complete::tag,
     character::complete::{alphanumeric1, one_of},
-    combinator::{opt, recognize},
-    multi::many1,
+    combinator::{map_opt, opt, recognize},
+    multi::many1_count,
     sequence::preceded,
 };
+use std::borrow::Cow;
 
-/// Parse the `path` component from the URI.
-pub(crate) fn database(i: &str) -> Res<&str, Option<String>> {
+/// Parse the `path` component from the URI, decoding any percent-encoded
+/// characters.
+pub(crate) fn database<'a, E: Error<'a>>(i: &'a str) -> Res<'a, Option<Spanned<Cow<'a, str>>>, E> {
     // We use a match statement to ensure we return `None` for the path even if
     // there's a trailing slash, because that doesn't make sense as a postgres
     // database name.
@@ -17,14 +20,17 @@ pub(crate) fn database(i: &str) -> Res<&str, Option<String>> {
     // TODO: Handle this better directly in nom.
     match opt(preceded(
         tag("/"),
-        opt(recognize(many1(alt((
-            alphanumeric1,
-            recognize(one_of("_-")),
-        ))))),
+        opt(spanned(map_opt(
+            recognize(many1_count(alt((
+                alphanumeric1,
+                recognize(one_of("-_.~")),
+                recognize(urlencoded1),
+            )))),
+            percent::decode,
+        ))),
     ))(i)
     {
-        Ok((r, Some(Some(p)))) if p.is_empty() => Ok((r, None)),
-        Ok((r, Some(Some(p)))) => Ok((r, Some(p.to_string()))),
+        Ok((r, Some(Some(p)))) => Ok((r, Some(p))),
         Ok((r, None | Some(None))) => Ok((r, None)),
         Err(e) => Err(e),
     }
@@ -33,20 +39,28 @@ pub(crate) fn database(i: &str) -> Res<&str, Option<String>> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::parser::{no_span, unspanned, Verbose};
 
     #[test]
     fn test_database() {
         // Good cases.
-        assert_eq!(database("/mypath"), Ok(("", Some("mypath".to_string()))));
         assert_eq!(
-            database("/db?host=/tmp"),
-            Ok(("?host=/tmp", Some("db".to_string())))
+            unspanned(database::<Verbose>("/mypath")),
+            Ok(("", Some(no_span("mypath".into()))))
         );
-        assert_eq!(database("/"), Ok(("", None)));
-        assert_eq!(database(""), Ok(("", None)));
         assert_eq!(
-            database("/mypath/again"),
-            Ok(("/again", Some("mypath".to_string())))
+            unspanned(database::<Verbose>("/db?host=/tmp")),
+            Ok(("?host=/tmp", Some(no_span("db".into()))))
+        );
+        assert_eq!(unspanned(database::<Verbose>("/")), Ok(("", None)));
+        assert_eq!(unspanned(database::<Verbose>("")), Ok(("", None)));
+        assert_eq!(
+            unspanned(database::<Verbose>("/my%20db.v2?a=b")),
+            Ok(("?a=b", Some(no_span("my db.v2".into()))))
+        );
+        assert_eq!(
+            unspanned(database::<Verbose>("/mypath/again")),
+            Ok(("/again", Some(no_span("mypath".into()))))
         );
     }
 }
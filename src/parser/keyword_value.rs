@@ -0,0 +1,183 @@
+/*!
+Parse libpq's keyword/value connection string form, e.g.
+`host=localhost port=5432 dbname=mydb`.
+
+See [https://www.postgresql.org/docs/current/libpq-connect.html#LIBPQ-CONNSTRING-KEYWORD-VALUE](https://www.postgresql.org/docs/current/libpq-connect.html#LIBPQ-CONNSTRING-KEYWORD-VALUE).
+*/
+
+use super::{check_param, consumed, spanned, Error, Res};
+use crate::ast::{KeywordValues, Parameter};
+use nom::{
+    branch::alt,
+    bytes::complete::{escaped_transform, is_not, tag},
+    character::complete::{alphanumeric1, anychar, multispace0, multispace1, one_of},
+    combinator::{cut, map, opt, recognize},
+    error::context,
+    multi::{many1_count, separated_list0},
+    sequence::{delimited, preceded, terminated},
+};
+use std::borrow::Cow;
+
+/// Parse a whitespace-separated list of `keyword=value` pairs, which may be
+/// empty, as libpq allows.
+pub(crate) fn keyword_values<'a, E: Error<'a>>(i: &'a str) -> Res<'a, KeywordValues<'a>, E> {
+    let (rem, parameters) = delimited(
+        multispace0,
+        separated_list0(multispace1, keyword_value),
+        multispace0,
+    )(i)?;
+
+    Ok((
+        rem,
+        KeywordValues {
+            span: consumed(i, rem),
+            parameters,
+        },
+    ))
+}
+
+/// Parse a single `keyword=value` pair. Whitespace is allowed around the `=`.
+fn keyword_value<'a, E: Error<'a>>(i: &'a str) -> Res<'a, Parameter<'a>, E> {
+    let (value_at, keyword) = terminated(
+        context("keyword", spanned(keyword)),
+        delimited(multispace0, tag("="), multispace0),
+    )(i)?;
+    let (rem, value) = context("value", spanned(value))(value_at)?;
+    check_param(keyword.value, &value.value, value_at)?;
+
+    Ok((
+        rem,
+        Parameter {
+            span: consumed(i, rem),
+            keyword,
+            value,
+        },
+    ))
+}
+
+/// Parse a `keyword`.
+fn keyword<'a, E: Error<'a>>(i: &'a str) -> Res<'a, &'a str, E> {
+    recognize(many1_count(alt((alphanumeric1, recognize(one_of("_-"))))))(i)
+}
+
+/// Parse a `value`, which is either single-quoted or runs until the next
+/// whitespace. In both forms a backslash escapes the following character.
+fn value<'a, E: Error<'a>>(i: &'a str) -> Res<'a, Cow<'a, str>, E> {
+    alt((quoted, unquoted))(i)
+}
+
+/// Parse a single-quoted value, e.g. `'my \'quoted\' value'`.
+fn quoted<'a, E: Error<'a>>(i: &'a str) -> Res<'a, Cow<'a, str>, E> {
+    preceded(
+        tag("'"),
+        cut(terminated(
+            |i| unescape(i, "\\'"),
+            context("closing quote", tag("'")),
+        )),
+    )(i)
+}
+
+/// Parse an unquoted value, which may be empty.
+fn unquoted<'a, E: Error<'a>>(i: &'a str) -> Res<'a, Cow<'a, str>, E> {
+    unescape(i, " \t\r\n\\")
+}
+
+/// Parse characters up to one of `special`, removing backslash escapes. The
+/// value is only copied if there are escapes to remove.
+fn unescape<'a, E: Error<'a>>(i: &'a str, special: &'static str) -> Res<'a, Cow<'a, str>, E> {
+    let (rem, raw) = opt(is_not(special))(i)?;
+    if !rem.starts_with('\\') {
+        return Ok((rem, Cow::Borrowed(raw.unwrap_or_default())));
+    }
+
+    map(
+        opt(escaped_transform(is_not(special), '\\', anychar)),
+        |value| Cow::Owned(value.unwrap_or_default()),
+    )(i)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::{no_span, unspanned, Verbose};
+    use nom::combinator::all_consuming;
+
+    #[test]
+    fn test_value() {
+        for (input, rem, expect) in [
+            ("localhost", "", "localhost"),
+            ("localhost port=5432", " port=5432", "localhost"),
+            ("''", "", ""),
+            ("", "", ""),
+            ("'my value'", "", "my value"),
+            (r"'it\'s'", "", "it's"),
+            (r"'back\\slash'", "", r"back\slash"),
+            (r"un\ quoted", "", "un quoted"),
+            ("/var/run/postgresql", "", "/var/run/postgresql"),
+            ("a=b", "", "a=b"),
+        ] {
+            assert_eq!(
+                value::<Verbose>(input),
+                Ok((rem, Cow::Borrowed(expect))),
+                "input: {input:?}"
+            );
+        }
+
+        for input in ["'unterminated", r"'trailing\'"] {
+            assert!(value::<Verbose>(input).is_err(), "input: {input:?}");
+        }
+    }
+
+    #[test]
+    fn test_keyword_values() {
+        assert_eq!(
+            unspanned(keyword_values::<Verbose>(
+                "  host=localhost port = 5432\tdbname='my db'  "
+            )),
+            Ok((
+                "",
+                KeywordValues {
+                    span: 0..0,
+                    parameters: vec![
+                        Parameter {
+                            span: 0..0,
+                            keyword: no_span("host"),
+                            value: no_span("localhost".into()),
+                        },
+                        Parameter {
+                            span: 0..0,
+                            keyword: no_span("port"),
+                            value: no_span("5432".into()),
+                        },
+                        Parameter {
+                            span: 0..0,
+                            keyword: no_span("dbname"),
+                            value: no_span("my db".into()),
+                        },
+                    ],
+                }
+            ))
+        );
+
+        for input in ["", "   "] {
+            assert_eq!(
+                unspanned(keyword_values::<Verbose>(input)),
+                Ok((
+                    "",
+                    KeywordValues {
+                        span: 0..0,
+                        parameters: vec![],
+                    }
+                )),
+                "input: {input:?}"
+            );
+        }
+
+        for input in ["=localhost", "host", "host=a =b"] {
+            assert!(
+                all_consuming(keyword_values::<Verbose>)(input).is_err(),
+                "input: {input:?}"
+            );
+        }
+    }
+}
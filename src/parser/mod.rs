@@ -1,85 +1,348 @@
-//! Functions and structures for parsing a valid PostgreSQL URI into an AST.
-//!
-//! This is useful if you want to see the raw parts of a URI, or how
-//! pg-connection-string is parsing it, but for most consumers the top-level
-//! functions are a better entrypoint.
+//! Functions for parsing a valid PostgreSQL URI into an [`ast`](crate::ast).
 pub(crate) mod authority;
 mod database;
+mod keyword_value;
 mod query;
 #[cfg(test)]
 mod tests;
 
-use crate::Parameter;
-use anyhow::{format_err, Result};
-use authority::{authority, Authority};
+use crate::{
+    ast::{ConnectionUri, KeywordValues, Spanned, Spans},
+    percent, ParseError,
+};
+use authority::authority;
 use database::database;
+use keyword_value::keyword_values;
 use nom::{
     branch::alt,
     bytes::complete::tag,
     character::complete::{alphanumeric1, one_of, space0},
-    combinator::{all_consuming, map, opt, recognize},
-    error::{context, VerboseError},
-    multi::{many1, separated_list1},
-    sequence::{delimited, preceded, terminated, tuple},
-    IResult,
+    combinator::{all_consuming, map_opt, opt, recognize},
+    error::{
+        context, ContextError, ErrorKind, FromExternalError, ParseError as NomParseError,
+        VerboseError, VerboseErrorKind,
+    },
+    multi::many0_count,
+    sequence::{delimited, terminated, tuple},
+    Finish, IResult,
 };
-use query::query;
+use query::{query, urlencoded1};
+use std::{borrow::Cow, net::AddrParseError, ops::Range};
 use tracing::debug;
 
-type Res<I, O> = IResult<I, O, VerboseError<I>>;
+type Res<'a, O, E> = IResult<&'a str, O, E>;
+
+/// The errors the parsers can return.
+///
+/// Inputs are parsed with nom's plain [`nom::error::Error`] first, which
+/// doesn't allocate as the parsers backtrack, and only parsed again with
+/// [`VerboseError`] to work out what's wrong if that fails.
+pub(crate) trait Error<'a>:
+    NomParseError<&'a str> + ContextError<&'a str> + FromExternalError<&'a str, AddrParseError>
+{
+}
 
-/// Struct representing the AST for a Postgres URI.
-#[derive(Debug, Default, PartialEq, Eq)]
-pub(crate) struct ConnectionUri {
-    pub authority: Option<Authority>,
-    pub database: Option<String>,
-    pub parameters: Option<Vec<Parameter>>,
-    pub fragment: Option<String>,
+impl<'a, E> Error<'a> for E where
+    E: NomParseError<&'a str> + ContextError<&'a str> + FromExternalError<&'a str, AddrParseError>
+{
 }
 
-pub(crate) fn multi_connection_string(i: &str, sep: &str) -> Result<Vec<ConnectionUri>> {
-    let (rem, res) = all_consuming(separated_list1(tag(sep), connection_string))(i)
-        .map_err(|e| format_err!("error consuming multi connection string: {}", e))?;
-    // eprintln!("input: {i:?}; rem: {rem:?}");
-    debug!("input: {i:?}; rem: {rem:?}");
+type Cheap<'a> = nom::error::Error<&'a str>;
 
-    Ok(res)
+#[cfg(test)]
+type Verbose<'a> = VerboseError<&'a str>;
+
+/// Clear the spans in a parser's output, to compare the rest of it.
+#[cfg(test)]
+fn unspanned<T: Spans>(mut node: T) -> T {
+    node.spans_mut(&mut |span| *span = 0..0);
+    node
+}
+
+/// A value with no span, to compare with [`unspanned`] output.
+#[cfg(test)]
+fn no_span<T>(value: T) -> Spanned<T> {
+    Spanned { value, span: 0..0 }
+}
+
+#[cfg(test)]
+impl<T: Spans> Spans for (&str, T) {
+    fn spans_mut(&mut self, f: &mut impl FnMut(&mut Range<usize>)) {
+        self.1.spans_mut(f);
+    }
+}
+
+#[cfg(test)]
+impl<T: Spans, E> Spans for Result<T, E> {
+    fn spans_mut(&mut self, f: &mut impl FnMut(&mut Range<usize>)) {
+        if let Ok(node) = self {
+            node.spans_mut(f);
+        }
+    }
 }
 
-pub(crate) fn consuming_connection_string(i: &str) -> Result<ConnectionUri> {
-    let (_, res) = all_consuming(connection_string)(i)
-        .map_err(|e| format_err!("error consuming connection string: {}", e))?;
+pub(crate) fn multi_connection_string<'a>(
+    i: &'a str,
+    sep: &str,
+) -> Result<Vec<ConnectionUri<'a>>, ParseError> {
+    let res = split_multi(i, sep)
+        .into_iter()
+        .map(|(base, piece)| {
+            let mut uri = consuming_connection_string(piece).map_err(|e| e.relocate(i, base))?;
+            uri.spans_mut(&mut |span| *span = base + span.start..base + span.end);
+            Ok(uri)
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    debug!(parsed = res.len(), "parsed connection strings");
 
     Ok(res)
 }
 
-fn connection_string(i: &str) -> Res<&str, ConnectionUri> {
-    map(
-        preceded(
-            context("scheme", scheme),
-            tuple((
-                opt(authority),
-                context("database", database),
-                context("query", query),
-                opt(fragment),
-            )),
-        ),
-        |(authority, database, parameters, fragment)| ConnectionUri {
+/// Split a list of URIs on the separator, returning each one along with its
+/// offset in the input. Query values may contain almost anything, including
+/// the separator, so we only split where the separator is followed by the next
+/// URI's scheme.
+fn split_multi<'a>(i: &'a str, sep: &str) -> Vec<(usize, &'a str)> {
+    let mut out = vec![];
+    let mut start = 0;
+
+    if !sep.is_empty() {
+        for (pos, _) in i.match_indices(sep) {
+            if pos >= start && has_uri_scheme(&i[pos + sep.len()..]) {
+                out.push((start, &i[start..pos]));
+                start = pos + sep.len();
+            }
+        }
+    }
+    out.push((start, &i[start..]));
+
+    out
+}
+
+pub(crate) fn consuming_connection_string(i: &str) -> Result<ConnectionUri<'_>, ParseError> {
+    if let Ok((_, res)) = all_consuming(connection_string::<Cheap>)(i) {
+        return Ok(locate(res, i));
+    }
+
+    let (_, res) = all_consuming(connection_string::<VerboseError<&str>>)(i)
+        .finish()
+        .map_err(|e| uri_error(i, &e))?;
+
+    Ok(locate(res, i))
+}
+
+pub(crate) fn consuming_keyword_value_string(i: &str) -> Result<KeywordValues<'_>, ParseError> {
+    if let Ok((_, res)) = all_consuming(keyword_values::<Cheap>)(i) {
+        return Ok(locate(res, i));
+    }
+
+    let (_, res) = all_consuming(keyword_values::<VerboseError<&str>>)(i)
+        .finish()
+        .map_err(|e| {
+            named_error(i, &e).unwrap_or_else(|| ParseError::KeywordValue {
+                input: i.to_string(),
+                offset: error_offset(i, &e),
+            })
+        })?;
+
+    Ok(locate(res, i))
+}
+
+/// Record the input a parser consumed along with its output.
+///
+/// Parsers only see the rest of the input, so spans are counted back from its
+/// end, with `start >= end`, until [`locate`] turns them into offsets.
+fn spanned<'a, O, E: Error<'a>>(
+    mut f: impl FnMut(&'a str) -> Res<'a, O, E>,
+) -> impl FnMut(&'a str) -> Res<'a, Spanned<O>, E> {
+    move |i| {
+        let (rem, value) = f(i)?;
+        Ok((
+            rem,
+            Spanned {
+                value,
+                span: consumed(i, rem),
+            },
+        ))
+    }
+}
+
+/// The span between the input a parser was given and what it left, counted
+/// back from the end like [`spanned`].
+fn consumed(i: &str, rem: &str) -> Range<usize> {
+    i.len()..rem.len()
+}
+
+/// Turn spans counted back from the end of the input into byte offsets.
+fn locate<T: Spans>(mut node: T, i: &str) -> T {
+    node.spans_mut(&mut |span| *span = i.len() - span.start..i.len() - span.end);
+    node
+}
+
+/// Check whether the input starts with a URI scheme designator, which is how
+/// libpq tells URIs and keyword/value strings apart.
+pub(crate) fn has_uri_scheme(i: &str) -> bool {
+    scheme::<Cheap>(i).is_ok()
+}
+
+/// Check whether the input looks like a URI with a scheme other than
+/// PostgreSQL's, e.g. `mysql://host`.
+pub(crate) fn has_other_scheme(i: &str) -> bool {
+    i.split_once("://").is_some_and(|(scheme, _)| {
+        !scheme.is_empty()
+            && scheme
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || "+-.".contains(c))
+    })
+}
+
+/// libpq gives `host` and `port` values a structure of their own, so we check
+/// them while we still know where in the input they are.
+///
+/// libpq reads an empty `host` entry as the default host, but there's no
+/// [`Host`](crate::Host) for that, so empty entries are rejected instead.
+fn check_param<'a, E: Error<'a>>(
+    keyword: &str,
+    value: &str,
+    at: &'a str,
+) -> Result<(), nom::Err<E>> {
+    let component = match keyword {
+        "host" if value.split(',').any(str::is_empty) => "host",
+        "port"
+            if value
+                .split(',')
+                .any(|p| !p.is_empty() && p.parse::<u16>().is_err()) =>
+        {
+            "port"
+        }
+        _ => return Ok(()),
+    };
+
+    Err(failure(component, at))
+}
+
+/// Fail unrecoverably, naming the component that's invalid.
+fn failure<'a, E: Error<'a>>(component: &'static str, at: &'a str) -> nom::Err<E> {
+    nom::Err::Failure(E::add_context(
+        at,
+        component,
+        E::from_error_kind(at, ErrorKind::Verify),
+    ))
+}
+
+/// Get the offset of the innermost error.
+fn error_offset(i: &str, e: &VerboseError<&str>) -> usize {
+    e.errors.first().map_or(0, |(rem, _)| i.len() - rem.len())
+}
+
+/// Build an error from the innermost parser context that names a component.
+fn named_error(i: &str, e: &VerboseError<&str>) -> Option<ParseError> {
+    e.errors.iter().find_map(|(_, kind)| match kind {
+        VerboseErrorKind::Context(context) => {
+            ParseError::from_context(context, i, error_offset(i, e))
+        }
+        _ => None,
+    })
+}
+
+/// Work out which component of a URI an error is in.
+///
+/// Most of the URI grammar is optional, so most errors surface as unconsumed
+/// input rather than a failure in a particular parser; in that case we look at
+/// where in the URI the unconsumed input starts.
+fn uri_error(i: &str, e: &VerboseError<&str>) -> ParseError {
+    if let Some(err) = named_error(i, e) {
+        return err;
+    }
+
+    let offset = error_offset(i, e);
+    let input = i.to_string();
+
+    let Ok((after_scheme, _)) = scheme::<Cheap>(i) else {
+        return ParseError::Scheme { input, offset: 0 };
+    };
+    let start = i.len() - after_scheme.len();
+    let end = start
+        + after_scheme
+            .find(['/', '?', '#'])
+            .unwrap_or(after_scheme.len());
+
+    if offset < start {
+        return ParseError::Scheme { input, offset };
+    }
+
+    if offset < end {
+        let authority = &i[start..end];
+        let at = offset - start;
+
+        // Anything before the last `@` is the userinfo.
+        if authority[at..].contains('@') {
+            return ParseError::Userinfo { input, offset };
+        }
+
+        // Otherwise it's in a host:port pair. Colons inside an IPv6 address's
+        // brackets don't count.
+        let pair_start = authority[..at].rfind([',', '@']).map_or(0, |n| n + 1);
+        let before = &authority[pair_start..at];
+        let before = before.rsplit(']').next().unwrap_or(before);
+        if authority[at..].starts_with(':') || (before.contains(':') && !before.contains('[')) {
+            return ParseError::Port { input, offset };
+        }
+
+        return ParseError::Host { input, offset };
+    }
+
+    match i[end..=offset.min(i.len() - 1)]
+        .rfind(['?', '#'])
+        .map(|n| &i[end + n..=end + n])
+    {
+        Some("#") => ParseError::Fragment { input, offset },
+        Some(_) => ParseError::Query { input, offset },
+        None => ParseError::Database { input, offset },
+    }
+}
+
+fn connection_string<'a, E: Error<'a>>(i: &'a str) -> Res<'a, ConnectionUri<'a>, E> {
+    let (rem, (scheme, authority, database, parameters, fragment)) = tuple((
+        context("scheme", spanned(scheme)),
+        opt(authority),
+        context("database", database),
+        context("query", query),
+        opt(fragment),
+    ))(i)?;
+
+    Ok((
+        rem,
+        ConnectionUri {
+            span: consumed(i, rem),
+            scheme: Spanned {
+                value: scheme.value,
+                // Leave out the `://`.
+                span: scheme.span.start..scheme.span.start - scheme.value.len(),
+            },
             authority,
             database,
             parameters,
-            fragment: fragment.map(std::convert::Into::into),
+            fragment,
         },
-    )(i)
+    ))
 }
 
-/// Parse the `fragment` component from the URI.
-fn fragment(i: &str) -> Res<&str, &str> {
+/// Parse the `fragment` component from the URI, decoding any percent-encoded
+/// characters.
+fn fragment<'a, E: Error<'a>>(i: &'a str) -> Res<'a, Spanned<Cow<'a, str>>, E> {
     delimited(
         context("fragment #", tag("#")),
         context(
             "fragment body",
-            recognize(many1(alt((alphanumeric1, recognize(one_of("-_")))))),
+            spanned(map_opt(
+                recognize(many0_count(alt((
+                    alphanumeric1,
+                    recognize(one_of("-._~")),
+                    urlencoded1,
+                )))),
+                percent::decode,
+            )),
         ),
         space0,
     )(i)
@@ -87,7 +350,7 @@ fn fragment(i: &str) -> Res<&str, &str> {
 
 /// Parse the scheme from the URI. This should always be either `postgres` or
 /// `postgresql`.
-fn scheme(i: &str) -> Res<&str, &str> {
+fn scheme<'a, E: Error<'a>>(i: &'a str) -> Res<'a, &'a str, E> {
     terminated(
         alt((postgresql, postgres)),
         context("scheme :// tag", tag("://")),
@@ -95,11 +358,11 @@ fn scheme(i: &str) -> Res<&str, &str> {
 }
 
 /// Recognize the tag `postgres`.
-fn postgres(i: &str) -> Res<&str, &str> {
+fn postgres<'a, E: Error<'a>>(i: &'a str) -> Res<'a, &'a str, E> {
     tag("postgres")(i)
 }
 
 /// Recognize the tag `postgresql`.`test_from_st`
-fn postgresql(i: &str) -> Res<&str, &str> {
+fn postgresql<'a, E: Error<'a>>(i: &'a str) -> Res<'a, &'a str, E> {
     tag("postgresql")(i)
 }
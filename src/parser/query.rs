@@ -1,16 +1,19 @@
-use super::Res;
-use crate::Parameter;
+use super::{check_param, consumed, failure, spanned, Error, Res};
+use crate::{
+    ast::{Parameter, Spanned},
+    percent,
+};
 use nom::{
     branch::alt,
-    bytes::complete::tag,
+    bytes::complete::{is_not, tag},
     character::complete::{alphanumeric1, one_of},
-    combinator::{map, opt, recognize},
-    multi::{many1, separated_list0},
-    sequence::{pair, preceded, separated_pair},
+    combinator::{opt, recognize},
+    multi::{many1_count, separated_list0},
+    sequence::{pair, preceded, terminated},
 };
 
 /// Parse the `query` component from the URI.
-pub(crate) fn query(i: &str) -> Res<&str, Option<Vec<Parameter>>> {
+pub(crate) fn query<'a, E: Error<'a>>(i: &'a str) -> Res<'a, Option<Vec<Parameter<'a>>>, E> {
     // We use a match statement to return None if the query list is empty.
     // This will happen when there's a dangling `?`, but no actual query params.
     //
@@ -27,43 +30,55 @@ pub(crate) fn query(i: &str) -> Res<&str, Option<Vec<Parameter>>> {
     }
 }
 
-/// Parse a key=val pair from a list of query params.
-fn keyval(i: &str) -> Res<&str, Parameter> {
-    map(separated_pair(key, tag("="), value), |(keyword, value)| {
+/// Parse a key=val pair from a list of query params, decoding any
+/// percent-encoded characters. As in libpq, the value may be empty.
+fn keyval<'a, E: Error<'a>>(i: &'a str) -> Res<'a, Parameter<'a>, E> {
+    let (value_at, keyword) = terminated(spanned(key), tag("="))(i)?;
+    let (rem, value) = opt(value)(value_at)?;
+    let value = value.unwrap_or_default();
+
+    let value = percent::decode(value).ok_or_else(|| failure("query", value_at))?;
+    check_param(keyword.value, &value, value_at)?;
+
+    Ok((
+        rem,
         Parameter {
-            keyword: keyword.into(),
-            value: value.into(),
-        }
-    })(i)
+            span: consumed(i, rem),
+            keyword,
+            value: Spanned {
+                value,
+                span: consumed(value_at, rem),
+            },
+        },
+    ))
 }
 
 /// Parse a `key` tag from the query params.
-fn key(i: &str) -> Res<&str, &str> {
-    recognize(many1(alt((alphanumeric1, recognize(one_of("_-"))))))(i)
+fn key<'a, E: Error<'a>>(i: &'a str) -> Res<'a, &'a str, E> {
+    recognize(many1_count(alt((alphanumeric1, recognize(one_of("_-"))))))(i)
 }
 
-/// Parse a `value` tag from the query params.
-fn value(i: &str) -> Res<&str, &str> {
-    recognize(many1(alt((
-        urlencoded1,
-        alphanumeric1,
-        recognize(one_of("_-/")),
-    ))))(i)
+/// Parse a `value` tag from the query params. As in libpq, anything but `&`,
+/// `=` and `#` is allowed, and `%` must start a valid percent-encoded byte.
+fn value<'a, E: Error<'a>>(i: &'a str) -> Res<'a, &'a str, E> {
+    recognize(many1_count(alt((urlencoded1, is_not("&=#%")))))(i)
 }
 
-fn urlencoded1(i: &str) -> Res<&str, &str> {
+pub(crate) fn urlencoded1<'a, E: Error<'a>>(i: &'a str) -> Res<'a, &'a str, E> {
     preceded(tag("%"), recognize(pair(hex, hex)))(i)
 }
 
-fn hex(i: &str) -> Res<&str, &str> {
+fn hex<'a, E: Error<'a>>(i: &'a str) -> Res<'a, &'a str, E> {
     recognize(one_of("0123456789abcdef0123456789ABCDEF"))(i)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::parser::{no_span, unspanned, Verbose};
 
     #[test]
+    #[allow(clippy::useless_vec)]
     fn test_value() {
         for (input, rem, expect, comment) in vec![
             (
@@ -85,10 +100,47 @@ mod tests {
                 "option flags with escapes",
             ),
             ("/var/lib/postgresql", "", "/var/lib/postgresql", "paths"),
+            // Parameter values from the examples in the PostgreSQL docs, and
+            // the sorts of values found in the wild.
+            ("/etc/ssl/ca.crt", "", "/etc/ssl/ca.crt", "file paths"),
+            (
+                "~/.postgresql/root.crt",
+                "",
+                "~/.postgresql/root.crt",
+                "home paths",
+            ),
+            (
+                r"C:\certs\root.crt",
+                "",
+                r"C:\certs\root.crt",
+                "windows paths",
+            ),
+            ("api.v2", "", "api.v2", "dots"),
+            ("10.0.0.1", "", "10.0.0.1", "ipv4 addresses"),
+            ("2001:db8::1", "", "2001:db8::1", "ipv6 addresses"),
+            ("host1,host2", "", "host1,host2", "host lists"),
+            ("5432,5433", "", "5432,5433", "port lists"),
+            ("verify-full", "", "verify-full", "sslmode"),
+            ("read-write", "", "read-write", "target_session_attrs"),
+            ("TLSv1.2", "", "TLSv1.2", "ssl_min_protocol_version"),
+            ("UTF8", "", "UTF8", "client_encoding"),
+            ("p@ss:w0rd!$*()+;", "", "p@ss:w0rd!$*()+;", "sub-delims"),
+            ("my app", "", "my app", "unescaped spaces"),
+            ("caf\u{e9}", "", "caf\u{e9}", "non-ascii"),
+            (
+                "-c%20statement_timeout%3D5s",
+                "",
+                "-c%20statement_timeout%3D5s",
+                "escaped options",
+            ),
+            ("myapp&sslmode=require", "&sslmode=require", "myapp", "&"),
+            ("b=c", "=c", "b", "="),
+            ("myapp#frag", "#frag", "myapp", "#"),
+            ("a%zz", "%zz", "a", "malformed escapes"),
         ] {
             assert_eq!(
-                value(input).unwrap(),
-                (rem, expect.into()),
+                value::<Verbose>(input).unwrap(),
+                (rem, expect),
                 "input: {:?} (testing {})",
                 input,
                 comment
@@ -99,17 +151,21 @@ mod tests {
     #[test]
     fn test_query() {
         assert_eq!(
-            query("?connect_timeout=10&application_name=myapp"),
+            unspanned(query::<Verbose>(
+                "?connect_timeout=10&application_name=myapp"
+            )),
             Ok((
                 "",
                 Some(vec![
                     Parameter {
-                        keyword: "connect_timeout".to_string(),
-                        value: "10".to_string()
+                        span: 0..0,
+                        keyword: no_span("connect_timeout"),
+                        value: no_span("10".into())
                     },
                     Parameter {
-                        keyword: "application_name".to_string(),
-                        value: "myapp".to_string()
+                        span: 0..0,
+                        keyword: no_span("application_name"),
+                        value: no_span("myapp".into())
                     },
                 ]),
             ))
@@ -117,15 +173,50 @@ mod tests {
     }
 
     #[test]
+    fn test_value_errors() {
+        for input in ["", "&a", "=a", "#a", "%zz", "%2"] {
+            assert!(value::<Verbose>(input).is_err(), "input: {input:?}");
+        }
+    }
+
+    #[test]
+    fn test_keyval() {
+        assert_eq!(
+            unspanned(keyval::<Verbose>("options=-c%20search_path%3Dfoo")),
+            Ok((
+                "",
+                Parameter {
+                    span: 0..0,
+                    keyword: no_span("options"),
+                    value: no_span("-c search_path=foo".into())
+                }
+            ))
+        );
+        assert_eq!(
+            unspanned(keyval::<Verbose>("application_name=&a=b")),
+            Ok((
+                "&a=b",
+                Parameter {
+                    span: 0..0,
+                    keyword: no_span("application_name"),
+                    value: no_span("".into())
+                }
+            ))
+        );
+        assert!(keyval::<Verbose>("options=%C3").is_err());
+    }
+
+    #[test]
+    #[allow(clippy::useless_vec)]
     fn test_urlencoded1() {
         for (input, rem, expect) in vec![
-            ("%20", "", "20".into()),
-            ("%3D", "", "3D".into()),
-            ("%3d", "", "3d".into()),
-            ("%3D1", "1", "3D".into()),
+            ("%20", "", "20"),
+            ("%3D", "", "3D"),
+            ("%3d", "", "3d"),
+            ("%3D1", "1", "3D"),
         ] {
             assert_eq!(
-                urlencoded1(input).unwrap(),
+                urlencoded1::<Verbose>(input).unwrap(),
                 (rem, expect),
                 "input: {:?}",
                 input,
@@ -0,0 +1,106 @@
+/*!
+Percent-encoding and decoding for URI components.
+*/
+
+use std::{
+    borrow::Cow,
+    fmt::{Display, Write},
+};
+
+/// Decode `%XX` escapes in a URI component, returning `None` if an escape is
+/// malformed or the result isn't valid UTF-8. The input is only copied if
+/// there's something to decode.
+pub(crate) fn decode(s: &str) -> Option<Cow<'_, str>> {
+    if !s.contains('%') {
+        return Some(Cow::Borrowed(s));
+    }
+
+    let mut bytes = Vec::with_capacity(s.len());
+    let mut rest = s.as_bytes();
+
+    while let Some((&b, tail)) = rest.split_first() {
+        if b != b'%' {
+            bytes.push(b);
+            rest = tail;
+            continue;
+        }
+
+        let hex = tail
+            .get(..2)
+            .and_then(|h| std::str::from_utf8(h).ok())
+            .and_then(|h| u8::from_str_radix(h, 16).ok())?;
+        bytes.push(hex);
+        rest = &tail[2..];
+    }
+
+    String::from_utf8(bytes).ok().map(Cow::Owned)
+}
+
+/// Display adapter that percent-encodes everything except RFC 3986 unreserved
+/// characters and the given extra characters.
+pub(crate) struct Encode<'a> {
+    value: &'a str,
+    extra: &'static str,
+}
+
+/// Percent-encode `value`, leaving the characters in `extra` as-is.
+pub(crate) fn encode<'a>(value: &'a str, extra: &'static str) -> Encode<'a> {
+    Encode { value, extra }
+}
+
+impl Display for Encode<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for c in self.value.chars() {
+            if c.is_ascii_alphanumeric() || "-._~".contains(c) || self.extra.contains(c) {
+                f.write_char(c)?;
+                continue;
+            }
+
+            let mut buf = [0; 4];
+            for b in c.encode_utf8(&mut buf).bytes() {
+                write!(f, "%{b:02X}")?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode() {
+        for (input, expect) in [
+            ("plain", "plain"),
+            ("%20", " "),
+            ("-c%20search_path%3Dfoo", "-c search_path=foo"),
+            ("%3d%3D", "=="),
+            ("caf%C3%A9", "café"),
+        ] {
+            assert_eq!(decode(input).unwrap(), expect, "input: {input:?}");
+        }
+
+        for input in ["%", "%2", "%zz", "100%", "%C3"] {
+            assert!(decode(input).is_none(), "input: {input:?}");
+        }
+
+        // Nothing is copied unless there's something to decode.
+        assert!(matches!(decode("plain"), Some(Cow::Borrowed("plain"))));
+    }
+
+    #[test]
+    fn test_encode() {
+        for (input, extra, expect) in [
+            ("plain-text_1.0~", "", "plain-text_1.0~"),
+            ("-c search_path=foo", "", "-c%20search_path%3Dfoo"),
+            ("/var/lib/postgresql", "/", "/var/lib/postgresql"),
+            ("/var/lib/postgresql", "", "%2Fvar%2Flib%2Fpostgresql"),
+            ("p@ss:w%rd", "", "p%40ss%3Aw%25rd"),
+            ("café", "", "caf%C3%A9"),
+        ] {
+            assert_eq!(encode(input, extra).to_string(), expect, "input: {input:?}");
+        }
+    }
+}
@@ -0,0 +1,557 @@
+/*!
+Read connection service files (`pg_service.conf`) and fill in connection
+parameters from the service they define.
+*/
+
+use crate::{defaults, env::Environment, ConnectionString, Parameter, ParseError, LIBPQ_KEYWORDS};
+use std::{
+    fmt::Display,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+use tracing::{debug, trace};
+
+/// A service defined in a connection service file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Service {
+    /// The service name, from the `[name]` section header.
+    pub name: String,
+
+    /// The connection parameters in the section, in the order they're given.
+    pub parameters: Vec<Parameter>,
+}
+
+/// A connection service file, such as `~/.pg_service.conf`.
+///
+/// The file is INI-style: a `[name]` line starts the definition of a service,
+/// and each `keyword=value` line after it sets a connection parameter. Blank
+/// lines and lines starting with `#` are ignored.
+///
+/// Parsing a file checks every section, but looking up a service for a
+/// connection string only checks the service's own section, as libpq does.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ServiceFile {
+    /// The services defined in the file, in the order they're given.
+    pub services: Vec<Service>,
+}
+
+impl ServiceFile {
+    /// Read and parse a service file.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file can't be read or is malformed.
+    pub fn read(path: impl AsRef<Path>) -> Result<Self, ServiceError> {
+        let path = path.as_ref();
+        let contents = std::fs::read_to_string(path).map_err(|source| ServiceError::Io {
+            path: path.into(),
+            source,
+        })?;
+
+        contents.parse().map_err(|e: ServiceError| e.at(path))
+    }
+
+    /// Parse a service file, checking every section, or with `only`, just
+    /// the first section with that name, which is then the only one kept.
+    fn parse(s: &str, only: Option<&str>) -> Result<Self, ServiceError> {
+        let mut services: Vec<Service> = vec![];
+        // Whether the lines being read belong to a section that's kept.
+        let mut keep = false;
+
+        for (n, line) in s.lines().enumerate() {
+            let line = line.trim();
+            let syntax = || ServiceError::Syntax {
+                path: PathBuf::new(),
+                line: n + 1,
+            };
+
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            if let Some(name) = line.strip_prefix('[') {
+                let name = name.strip_suffix(']');
+                keep = match only {
+                    None => true,
+                    // The first definition is the one that's used.
+                    Some(_) if !services.is_empty() => break,
+                    // Like libpq, a malformed header just isn't the one
+                    // that's wanted.
+                    Some(only) => name == Some(only),
+                };
+
+                if keep {
+                    services.push(Service {
+                        name: name.ok_or_else(syntax)?.to_string(),
+                        parameters: vec![],
+                    });
+                }
+                continue;
+            }
+
+            // Like libpq, ignore anything before the first section.
+            let Some(service) = services.last_mut().filter(|_| keep) else {
+                continue;
+            };
+
+            let (keyword, value) = line.split_once('=').ok_or_else(syntax)?;
+            let keyword = keyword.trim_end();
+
+            // Services can't refer to other services.
+            if keyword == "service" || !LIBPQ_KEYWORDS.contains(&keyword) {
+                return Err(ServiceError::InvalidKeyword {
+                    path: PathBuf::new(),
+                    line: n + 1,
+                    keyword: keyword.to_string(),
+                });
+            }
+
+            service.parameters.push(Parameter {
+                keyword: keyword.to_string(),
+                value: value.trim_start().to_string(),
+            });
+        }
+
+        Ok(ServiceFile { services })
+    }
+
+    /// Get a service by name. As in libpq, if a name is defined more than
+    /// once, the first definition is used.
+    #[must_use]
+    pub fn get(&self, name: &str) -> Option<&Service> {
+        self.services.iter().find(|s| s.name == name)
+    }
+}
+
+impl FromStr for ServiceFile {
+    type Err = ServiceError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        ServiceFile::parse(s, None)
+    }
+}
+
+/// Where the values filled in from a service came from.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ServiceSource {
+    /// The name of the service.
+    pub service: String,
+
+    /// The service file the service was found in.
+    pub path: PathBuf,
+
+    /// The keywords whose values were taken from the service. Keywords the
+    /// connection string already had aren't included.
+    pub keywords: Vec<String>,
+}
+
+/// An error resolving a connection service.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum ServiceError {
+    /// A service file couldn't be read.
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    /// A line in a service file is neither a section header nor a
+    /// `keyword=value` pair. `path` is empty if the file was parsed from a
+    /// string.
+    Syntax { path: PathBuf, line: usize },
+    /// A line in a service file sets a keyword libpq doesn't know, or
+    /// `service` itself.
+    InvalidKeyword {
+        path: PathBuf,
+        line: usize,
+        keyword: String,
+    },
+    /// The service isn't defined in any service file.
+    NotFound { service: String },
+    /// The service's `host` or `port` list is invalid.
+    Invalid { path: PathBuf, source: ParseError },
+}
+
+impl ServiceError {
+    /// Attach the path of the file that was being parsed.
+    fn at(mut self, file: &Path) -> Self {
+        if let ServiceError::Syntax { path, .. } | ServiceError::InvalidKeyword { path, .. } =
+            &mut self
+        {
+            *path = file.into();
+        }
+
+        self
+    }
+}
+
+impl Display for ServiceError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // Files parsed from a string don't have a path.
+        let file = |path: &Path| match path.as_os_str().is_empty() {
+            true => "service file".to_string(),
+            false => format!("service file {}", path.display()),
+        };
+
+        match self {
+            ServiceError::Io { path, source } => {
+                write!(
+                    f,
+                    "could not read service file {}: {source}",
+                    path.display()
+                )
+            }
+            ServiceError::Syntax { path, line } => {
+                write!(f, "syntax error in {}, line {line}", file(path))
+            }
+            ServiceError::InvalidKeyword {
+                path,
+                line,
+                keyword,
+            } => write!(
+                f,
+                "invalid keyword {keyword:?} in {}, line {line}",
+                file(path)
+            ),
+            ServiceError::NotFound { service } => {
+                write!(f, "definition of service {service:?} not found")
+            }
+            ServiceError::Invalid { path, source } => {
+                write!(f, "in {}: {source}", file(path))
+            }
+        }
+    }
+}
+
+impl std::error::Error for ServiceError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ServiceError::Io { source, .. } => Some(source),
+            ServiceError::Invalid { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+/// The service files to search, in order: the user's file, then the system
+/// one.
+fn service_files(env: &impl Environment) -> Vec<PathBuf> {
+    let mut files = vec![];
+
+    if let Some(path) = env.var("PGSERVICEFILE") {
+        files.push(path.into());
+    } else if let Some(home) = env.var(if cfg!(windows) { "APPDATA" } else { "HOME" }) {
+        let mut path = PathBuf::from(home);
+        if cfg!(windows) {
+            path.push("postgresql");
+        }
+        path.push(".pg_service.conf");
+        files.push(path);
+    }
+
+    if let Some(dir) = env.var("PGSYSCONFDIR") {
+        files.push(Path::new(&dir).join("pg_service.conf"));
+    }
+
+    files
+}
+
+/// Fill in anything missing from `conn` from the service it names, with the
+/// `service` parameter or `PGSERVICE`.
+///
+/// As in libpq, the service is looked for in `PGSERVICEFILE` (or
+/// `~/.pg_service.conf`), then in `pg_service.conf` in `PGSYSCONFDIR`; the
+/// first file that defines it is used. Missing files are skipped.
+pub(crate) fn apply_service(
+    conn: &mut ConnectionString,
+    env: &impl Environment,
+) -> Result<Option<ServiceSource>, ServiceError> {
+    let name = match conn
+        .parameters
+        .iter()
+        .rev()
+        .find(|p| p.keyword == "service")
+    {
+        Some(p) => p.value.clone(),
+        None => match env.var("PGSERVICE") {
+            Some(name) => name,
+            None => return Ok(None),
+        },
+    };
+
+    for path in service_files(env) {
+        let contents = match std::fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                trace!(path = %path.display(), "no service file");
+                continue;
+            }
+            Err(source) => return Err(ServiceError::Io { path, source }),
+        };
+        let file = ServiceFile::parse(&contents, Some(&name)).map_err(|e| e.at(&path))?;
+
+        let Some(service) = file.get(&name) else {
+            continue;
+        };
+        debug!(service = name, path = %path.display(), "found service");
+
+        return match defaults::fill_missing(conn, &service.parameters) {
+            Ok(keywords) => Ok(Some(ServiceSource {
+                service: name,
+                path,
+                keywords,
+            })),
+            Err(source) => Err(ServiceError::Invalid { path, source }),
+        };
+    }
+
+    Err(ServiceError::NotFound { service: name })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::HostSpec;
+    use std::collections::HashMap;
+
+    /// Write service files to a fresh directory, returning its path.
+    fn write_files(test: &str, files: &[(&str, &str)]) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("postgres-conn-str-{}-{test}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+
+        for (name, contents) in files {
+            std::fs::write(dir.join(name), contents).unwrap();
+        }
+
+        dir
+    }
+
+    fn env(dir: &Path, vars: &[(&str, &str)]) -> HashMap<String, String> {
+        let mut env = vars
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect::<HashMap<_, _>>();
+        env.insert("HOME".into(), dir.display().to_string());
+        env.insert("APPDATA".into(), dir.display().to_string());
+
+        env
+    }
+
+    #[test]
+    fn test_parse() {
+        let file: ServiceFile = "
+# Ignored
+ignored=yes
+
+[analytics]
+host=db1,db2
+port = 5433
+  dbname=warehouse
+options=-c search_path=a,b
+
+[empty]
+"
+        .parse()
+        .unwrap();
+
+        assert_eq!(
+            file.services,
+            [
+                Service {
+                    name: "analytics".into(),
+                    parameters: vec![
+                        Parameter {
+                            keyword: "host".into(),
+                            value: "db1,db2".into()
+                        },
+                        Parameter {
+                            keyword: "port".into(),
+                            value: "5433".into()
+                        },
+                        Parameter {
+                            keyword: "dbname".into(),
+                            value: "warehouse".into()
+                        },
+                        Parameter {
+                            keyword: "options".into(),
+                            value: "-c search_path=a,b".into()
+                        },
+                    ],
+                },
+                Service {
+                    name: "empty".into(),
+                    parameters: vec![],
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        for (input, expect) in [
+            ("[svc]\nhost", "syntax error in service file, line 2"),
+            ("[svc\nhost=a", "syntax error in service file, line 1"),
+            (
+                "[svc]\nservice=other",
+                r#"invalid keyword "service" in service file, line 2"#,
+            ),
+            (
+                "\n[svc]\nhots=a",
+                r#"invalid keyword "hots" in service file, line 3"#,
+            ),
+        ] {
+            assert_eq!(
+                input.parse::<ServiceFile>().unwrap_err().to_string(),
+                expect,
+                "input: {input:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_apply_service() {
+        let dir = write_files(
+            "apply",
+            &[
+                (
+                    ".pg_service.conf",
+                    "[analytics]\nhost=db1,db2\nport=5433\ndbname=warehouse\nsslmode=require\n",
+                ),
+                ("pg_service.conf", "[system]\nhost=sysdb\n"),
+            ],
+        );
+        let env = env(&dir, &[("PGSYSCONFDIR", &dir.display().to_string())]);
+
+        // The connection string wins over the service.
+        let conn: ConnectionString = "postgresql:///mydb?service=analytics&sslmode=disable"
+            .parse()
+            .unwrap();
+        let (conn, source) = conn.with_service_from(&env).unwrap();
+        assert_eq!(
+            conn.to_string(),
+            "postgresql://db1:5433,db2:5433/mydb?service=analytics&sslmode=disable"
+        );
+        assert_eq!(
+            source,
+            Some(ServiceSource {
+                service: "analytics".into(),
+                path: dir.join(".pg_service.conf"),
+                keywords: vec!["host".into(), "port".into()],
+            })
+        );
+
+        // PGSERVICE names a service in the system file.
+        let mut env = env;
+        env.insert("PGSERVICE".into(), "system".into());
+        let (conn, source) = ConnectionString::default().with_service_from(&env).unwrap();
+        assert_eq!(
+            conn.hostspecs,
+            [HostSpec {
+                host: "sysdb".parse().unwrap(),
+                port: None
+            }]
+        );
+        assert_eq!(source.unwrap().path, dir.join("pg_service.conf"));
+
+        // No service, nothing to do.
+        env.remove("PGSERVICE");
+        assert_eq!(
+            ConnectionString::default().with_service_from(&env).unwrap(),
+            (ConnectionString::default(), None)
+        );
+    }
+
+    #[test]
+    fn test_apply_service_files() {
+        let dir = write_files(
+            "files",
+            &[
+                ("custom.conf", "[svc]\ndbname=custom\n"),
+                (".pg_service.conf", "[svc]\ndbname=home\n"),
+                ("bad.conf", "[svc]\nport=99999\n"),
+                (
+                    "other.conf",
+                    "[newer]\nfuture_keyword=1\n[broken\nnot a pair\n\
+                     [svc]\ndbname=other\n[svc]\nunknown=x\n",
+                ),
+                ("unknown.conf", "[other]\ndbname=x\n[svc]\nunknown=x\n"),
+            ],
+        );
+        let conn: ConnectionString = "service=svc".parse().unwrap();
+
+        // PGSERVICEFILE replaces the file in the home directory.
+        let (conn_out, _) = conn
+            .with_service_from(&env(
+                &dir,
+                &[(
+                    "PGSERVICEFILE",
+                    &dir.join("custom.conf").display().to_string(),
+                )],
+            ))
+            .unwrap();
+        assert_eq!(conn_out.database.as_deref(), Some("custom"));
+
+        let (conn_out, _) = conn.with_service_from(&env(&dir, &[])).unwrap();
+        assert_eq!(conn_out.database.as_deref(), Some("home"));
+
+        // Only the service's own section is checked, and only the first
+        // definition of it is used.
+        let (conn_out, _) = conn
+            .with_service_from(&env(
+                &dir,
+                &[(
+                    "PGSERVICEFILE",
+                    &dir.join("other.conf").display().to_string(),
+                )],
+            ))
+            .unwrap();
+        assert_eq!(conn_out.database.as_deref(), Some("other"));
+
+        let err = conn
+            .with_service_from(&env(
+                &dir,
+                &[(
+                    "PGSERVICEFILE",
+                    &dir.join("unknown.conf").display().to_string(),
+                )],
+            ))
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "invalid keyword \"unknown\" in service file {}, line 4",
+                dir.join("unknown.conf").display()
+            )
+        );
+
+        // Missing files are skipped, but the service has to be somewhere.
+        let err = conn
+            .with_service_from(&env(
+                &dir,
+                &[(
+                    "PGSERVICEFILE",
+                    &dir.join("missing.conf").display().to_string(),
+                )],
+            ))
+            .unwrap_err();
+        assert!(matches!(err, ServiceError::NotFound { service } if service == "svc"));
+
+        let err = conn
+            .with_service_from(&env(
+                &dir,
+                &[("PGSERVICEFILE", &dir.join("bad.conf").display().to_string())],
+            ))
+            .unwrap_err();
+        assert!(
+            matches!(
+                err,
+                ServiceError::Invalid {
+                    source: ParseError::Port { .. },
+                    ..
+                }
+            ),
+            "error: {err}"
+        );
+    }
+}